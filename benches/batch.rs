@@ -0,0 +1,42 @@
+// Compares `parse_many` with parsing the same column one value at a time.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use eisel_lemire::batch::parse_many;
+use eisel_lemire::elparse::parse_float_bytes;
+
+// A column as exported from a spreadsheet: prices, measurements, ratios and
+// full-precision doubles. All are short and plain, so `parse_many` takes the
+// plain path for every one.
+fn column() -> Vec<String> {
+    (0..10_000u32).map(|i| match i % 5 {
+        0 => format!("{}.{:02}", i * 37 % 10_000, i % 100),
+        1 => format!("-{}.{}", i % 97, i * 7919),
+        2 => format!("{}e-{}", i * 31, i % 20),
+        3 => format!("0.{:015}", u64::from(i) * 104_729_000_000),
+        _ => format!("{:e}", -f64::from(i) / 7.0e10),
+    }).collect()
+}
+
+fn bench_batch(c: &mut Criterion) {
+    let column = column();
+    let inputs: Vec<&[u8]> = column.iter().map(|s| s.as_bytes()).collect();
+    let bytes: usize = inputs.iter().map(|i| i.len()).sum();
+    let mut out = vec![0.0f64; inputs.len()];
+
+    let mut group = c.benchmark_group("column");
+    group.throughput(Throughput::Bytes(bytes as u64));
+    group.bench_function("one_at_a_time", |b| {
+        b.iter(|| {
+            for (input, out) in inputs.iter().zip(out.iter_mut()) {
+                *out = parse_float_bytes(black_box(input)).unwrap();
+            }
+        })
+    });
+    group.bench_function("parse_many", |b| {
+        b.iter(|| assert!(parse_many(black_box(&inputs), &mut out).is_empty()))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_batch);
+criterion_main!(benches);
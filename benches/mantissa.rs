@@ -0,0 +1,43 @@
+// Times `parse_float` on short inputs, where the eight-digits-at-a-time
+// (SWAR) mantissa loop should not get in the way, and on the 15 to 17
+// significant digit inputs that shortest round-trip printing of f64 usually
+// produces, where it should help. Compare runs before and after a change to
+// the mantissa loop.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use eisel_lemire::elparse::parse_float;
+
+const SHORT: [&str; 8] = ["12.375", "0.5", "-7", "1e10", "3.25", "100", "-0.125", "42.0"];
+
+const LONG: [&str; 8] = [
+    "0.1234567890123456",
+    "3.141592653589793",
+    "2.718281828459045",
+    "1.7976931348623157e308",
+    "2.2250738585072014e-308",
+    "123456789.12345678",
+    "0.30000000000000004",
+    "9007199254740993",
+];
+
+fn bench_inputs(c: &mut Criterion, name: &str, inputs: &[&str]) {
+    let bytes: usize = inputs.iter().map(|i| i.len()).sum();
+    let mut group = c.benchmark_group("parse_float");
+    group.throughput(Throughput::Bytes(bytes as u64));
+    group.bench_function(name, |b| {
+        b.iter(|| {
+            for i in inputs.iter() {
+                black_box(parse_float::<f64>(black_box(i)).unwrap());
+            }
+        })
+    });
+    group.finish();
+}
+
+fn bench_mantissa(c: &mut Criterion) {
+    bench_inputs(c, "short", &SHORT);
+    bench_inputs(c, "long", &LONG);
+}
+
+criterion_group!(benches, bench_mantissa);
+criterion_main!(benches);
@@ -1,10 +1,9 @@
 use num_bigint::BigUint;
 use num_traits::{One, Zero};
-use std::convert::{TryInto, From};
+use std::convert::{TryFrom, TryInto, From};
 use std::fs::File;
 use std::{env, path};
 use std::io::Write;
-use std::num::Wrapping;
 
 struct LUTEntry {
     m128_hi: u64,
@@ -14,8 +13,11 @@ struct LUTEntry {
 
 // Magic numbers for Eisel-Lemire table generation
 
-// N is large enough so that (1<<N) is easily bigger than 1e310.
-const N: i32 = 2048;
+// For each power of ten we start from 2 ** N with N large enough that
+// 2 ** N / 10 ** |e10| still has well over 128 bits: log2(10) < 4.
+fn start_pow2(e10: i16) -> i32 {
+    256 + 4 * i32::from(e10.unsigned_abs())
+}
 
 // 1214 is 1023 + 191. 1023 is the bias for IEEE 754 double-precision floating
 // point. 191 is ((3 * 64) - 1) and we work with multiples-of-64-bit mantissas.
@@ -26,13 +28,19 @@ fn gen_lookup_table(min_exponent: i16, max_exponent:i16) -> Vec<LUTEntry> {
     let two128: BigUint = One::one();
     let two128: BigUint = two128 << 128;
     assert!(two128.bits() == 129);
-    (min_exponent..=max_exponent).into_iter().map(|e10| gen_lut_entry(e10, &two128)).collect()
+    (min_exponent..=max_exponent).map(|e10| gen_lut_entry(e10, &two128)).collect()
 }
 
+// The widest table range we allow. lookups::get_widebiased_e2 is exact well
+// beyond it (including the small table's anchor below -5000), and its result
+// fits in an i16.
+const E10_LIMIT: i16 = 5000;
+
 fn gen_lut_entry(e10: i16, two128: &BigUint) -> LUTEntry {
-    assert!((-350i16..=310i16).contains(&e10), "E10 is out of range!");
+    assert!((-E10_LIMIT - ANCHOR_STEP..=E10_LIMIT).contains(&e10), "E10 is out of range!");
+    let n = start_pow2(e10);
     let mut z: BigUint = One::one();
-    z <<= N;  // Exp is now larger than 10^e10 for sure
+    z <<= n;  // Exp is now larger than 10^e10 for sure
 
     // Multiply z by 10^e10 using integer arithmetic. Since we can't actually
     // do 10^(negative) with integer arithmetic, implement as 10^(abs(e10))
@@ -40,27 +48,29 @@ fn gen_lut_entry(e10: i16, two128: &BigUint) -> LUTEntry {
     if e10 >= 0 {
         let e10:u32 = e10.abs().try_into().unwrap();
         let mult_val = BigUint::from(10u8).pow(e10);
-        z = z * mult_val;
+        z *= mult_val;
     } else {
         let e10:u32 = e10.abs().try_into().unwrap();
         let div_val = BigUint::from(10u8).pow(e10);
         assert!(div_val != Zero::zero(), "Division value is zero on input of {}", e10);
-        z = z / div_val;
+        z /= div_val;
     }
 
-    // Pow2 exponent
-    let mut e2 = -N;
-    while &z >= two128 {
-        z >>= 1;
-        e2 += 1;
+    // Pow2 exponent: shift down to exactly 128 bits
+    let mut e2 = -n;
+    if &z >= two128 {
+        let excess = z.bits() - 128;
+        z >>= excess;
+        e2 += i32::try_from(excess).unwrap();
     }
     assert!(z.bits() == 128, "Invalid representation of M128: wrong number of bits for 10^{}: {}!", e10, z.bits());
 
     // Check validity of exponent
-    let approx_n = ((Wrapping(217706u64) * Wrapping(e10 as u64)).0 >> 16) + 1087;
-    let approx_n = approx_n as u32;
+    // This is the same approximation used by lookups::get_widebiased_e2. It
+    // goes negative below 1e-327, so use signed arithmetic throughout.
+    let approx_n = ((14267572527i64 * i64::from(e10)) >> 32) + 1087;
     let bias_n = e2 + BIAS;
-    assert!(approx_n == bias_n.try_into().unwrap(), "Approxmiated exponent {} does not match biased exponent {}!", approx_n, bias_n);
+    assert!(approx_n == i64::from(bias_n), "Approxmiated exponent {} does not match biased exponent {}!", approx_n, bias_n);
 
 
     let digits = z.iter_u64_digits().collect::<Vec<_>>();
@@ -77,28 +87,141 @@ fn gen_lut_entry(e10: i16, two128: &BigUint) -> LUTEntry {
 
 /// Formats a generated lookup table into a string that can be included into 
 /// Rust source code as a static LUT. Also generates appropriate methods.
-fn format_lookup_table(luts: Vec<LUTEntry>, min_exponent: i16) -> String {
+/// With a `width` of 64 only the high halves of the entries are kept.
+fn format_lookup_table(luts: Vec<LUTEntry>, min_exponent: i16, width: u32) -> String {
     let mut lines = Vec::new();
     let numel: i16 = luts.len().try_into().unwrap();
 
     // Generate constants
     lines.push(format!("const EL_POW10_LUT_MIN: i16 = {};", min_exponent));
     lines.push(format!("const EL_POW10_LUT_MAX: i16 = {};", min_exponent + numel - 1));
-    lines.push(format!("const EL_POW10_LUT: [(u64, u64); {}] = [", luts.len()));
-    for entry in luts {
+    lines.push(format!("const EL_POW10_LUT_WIDTH: u32 = {};", width));
+    if width == 64 {
+        lines.push(format!("static EL_POW10_LUT: [u64; {}] = [", luts.len()));
+        for entry in luts {
+            lines.push(format!("{:#x}, // pow2 = {} ", entry.m128_hi, entry.widebiased_e2 - BIAS + 64));
+        }
+    } else {
+        lines.push(format!("static EL_POW10_LUT: [(u64, u64); {}] = [", luts.len()));
+        for entry in luts {
+            lines.push(format!("({:#x}, {:#x}), // pow2 = {} ", entry.m128_hi, entry.m128_lo, entry.widebiased_e2 - BIAS));
+        }
+    }
+    lines.push("];".to_string());
+
+    lines.join("\n")
+}
+
+// The small table keeps the powers 10 ** (ANCHOR_STEP * k). 5 ** 26 < 2 ** 61,
+// so the powers of five in between fit in a u64. With 10 ** 0 an anchor,
+// 10 ** 0 to 10 ** 26 come out exact; otherwise a truncated anchor could put
+// them just below a power of two, and normalize differently.
+const ANCHOR_STEP: i16 = 27;
+
+/// Reconstructs a power of ten from the anchor below it and a power of five,
+/// exactly as `lookups::get_m128` does in the small table mode, before the
+/// correction.
+fn reconstruct(anchor: &LUTEntry, pow5: u64) -> u128 {
+    let lo_prod = u128::from(anchor.m128_lo) * u128::from(pow5);
+    let top = u128::from(anchor.m128_hi) * u128::from(pow5) + (lo_prod >> 64);
+    let lz = top.leading_zeros();
+    (top << lz) | u128::from((lo_prod as u64) >> (64 - lz))
+}
+
+/// Formats the small table: the anchor powers, the powers of five in
+/// between, and for every power the correction (0 to 3) to add after
+/// reconstructing it, two bits each.
+fn format_small_table(luts: Vec<LUTEntry>, min_exponent: i16) -> String {
+    let mut lines = Vec::new();
+    let numel: i16 = luts.len().try_into().unwrap();
+    let max_exponent = min_exponent + numel - 1;
+    let pow5: Vec<u64> = (0..ANCHOR_STEP).map(|r| 5u64.pow(r.try_into().unwrap())).collect();
+    let anchor_min = min_exponent - min_exponent.rem_euclid(ANCHOR_STEP);
+    let two128: BigUint = BigUint::one() << 128;
+    let step = usize::try_from(ANCHOR_STEP).unwrap();
+    let anchors: Vec<LUTEntry> = (anchor_min..=max_exponent).step_by(step)
+        .map(|e10| gen_lut_entry(e10, &two128)).collect();
+
+    let mut corrections = vec![0u64; luts.len().div_ceil(32)];
+    for (index, entry) in luts.iter().enumerate() {
+        let e10 = min_exponent + i16::try_from(index).unwrap();
+        let offset = usize::try_from(e10 - anchor_min).unwrap();
+        let exact = (u128::from(entry.m128_hi) << 64) | u128::from(entry.m128_lo);
+        let approx = reconstruct(&anchors[offset / step], pow5[offset % step]);
+        // Truncating the anchor can only make the product low
+        let correction = exact.checked_sub(approx).expect("Reconstructed power is too high");
+        assert!(correction < 4, "Correction {} for 10^{} does not fit in two bits", correction, e10);
+        corrections[index / 32] |= u64::try_from(correction).unwrap() << (2 * (index % 32));
+    }
+
+    lines.push(format!("const EL_POW10_LUT_MIN: i16 = {};", min_exponent));
+    lines.push(format!("const EL_POW10_LUT_MAX: i16 = {};", max_exponent));
+    lines.push("const EL_POW10_LUT_WIDTH: u32 = 128;".to_string());
+    lines.push(format!("const EL_POW10_ANCHOR_MIN: i16 = {};", anchor_min));
+    lines.push(format!("const EL_POW10_ANCHOR_STEP: i16 = {};", ANCHOR_STEP));
+    lines.push(format!("static EL_POW10_ANCHORS: [(u64, u64); {}] = [", anchors.len()));
+    for entry in &anchors {
         lines.push(format!("({:#x}, {:#x}), // pow2 = {} ", entry.m128_hi, entry.m128_lo, entry.widebiased_e2 - BIAS));
     }
     lines.push("];".to_string());
+    lines.push(format!("static EL_POW5: [u64; {}] = [", pow5.len()));
+    for p in pow5 {
+        lines.push(format!("{:#x},", p));
+    }
+    lines.push("];".to_string());
+    lines.push(format!("static EL_POW10_CORRECTIONS: [u64; {}] = [", corrections.len()));
+    for c in corrections {
+        lines.push(format!("{:#018x},", c));
+    }
+    lines.push("];".to_string());
 
-    return lines.join("\n");
+    lines.join("\n")
+}
+
+/// Reads an optional setting from the environment, panicking with a useful
+/// message if it is set but malformed.
+fn env_setting<T: std::str::FromStr>(name: &str) -> Option<T> {
+    println!("cargo:rerun-if-env-changed={}", name);
+    let value = env::var(name).ok()?;
+    Some(value.trim().parse().unwrap_or_else(|_| panic!("{} must be an integer, not {:?}", name, value)))
 }
 
 fn main(){
     println!("cargo:rerun-if-changed=build.rs");
-    let min_exponent = -325;
-    let max_exponent = 308;
+    println!("cargo:rustc-check-cfg=cfg(eisel_lemire_lut64)");
+    // Mantissas have up to 19 digits, so we need powers well below the
+    // smallest subnormal (4.9e-324) to decide when a result underflows.
+    // Binary128 has a much wider exponent range. Either range can be
+    // overridden, e.g. to -65 and 38 for f32 alone; powers outside the table
+    // are left to the slower decimal algorithm.
+    let (default_min, default_max) = if env::var_os("CARGO_FEATURE_F128").is_some() {
+        (-4984, 4932)
+    } else {
+        (-342, 308)
+    };
+    let min_exponent = env_setting("EISEL_LEMIRE_LUT_MIN").unwrap_or(default_min);
+    let max_exponent = env_setting("EISEL_LEMIRE_LUT_MAX").unwrap_or(default_max);
+    assert!(-E10_LIMIT <= min_exponent && min_exponent <= max_exponent && max_exponent <= E10_LIMIT,
+            "The power-of-ten table range {}..={} must be nonempty and within -{}..={}",
+            min_exponent, max_exponent, E10_LIMIT, E10_LIMIT);
+
+    // Keeping only the high 64 bits of each power halves the table. f32 and
+    // narrower never need the rest; f64 rarely does, and falls back to the
+    // decimal algorithm when it would.
+    let width = env_setting("EISEL_LEMIRE_LUT_WIDTH").unwrap_or(128);
+    assert!(width == 64 || width == 128, "EISEL_LEMIRE_LUT_WIDTH must be 64 or 128, not {}", width);
+    let small_table = env::var_os("CARGO_FEATURE_SMALL_TABLE").is_some();
+    assert!(!(small_table && width == 64), "The small table needs 128-bit anchors; EISEL_LEMIRE_LUT_WIDTH=64 is not supported with it");
+    if width == 64 {
+        println!("cargo:rustc-cfg=eisel_lemire_lut64");
+    }
+
     let table = gen_lookup_table(min_exponent, max_exponent);
-    let lut_str = format_lookup_table(table, min_exponent);
+    let lut_str = if small_table {
+        format_small_table(table, min_exponent)
+    } else {
+        format_lookup_table(table, min_exponent, width)
+    };
 
     let out_dir = env::var("OUT_DIR").unwrap();
     let dest_path = path::Path::new(&out_dir).join("el_lookup_table.rs");
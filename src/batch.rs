@@ -0,0 +1,223 @@
+/* Parsing many floats at once, as from a column of a text export.
+
+Most values in such data are short and plain: a sign, a few digits with a
+point, and perhaps an exponent. For those, the work that dominates a
+one-at-a-time parse is the general front end, which handles every grammar
+option byte by byte. Here we instead classify each short input in one pass:
+which bytes are digits, points, exponent markers and signs. The bit masks then
+tell whether it is plain and where its integer, fraction and exponent digits
+are, and those runs are converted eight digits at a time before going straight
+to `float_from_man_exp10`.
+
+Everything else, including every invalid input, goes through
+`parse_float_bytes`, so the values and errors are exactly those of parsing
+each input on its own.
+
+Classifying with SSE2 or AVX2 compares would need the inputs packed into
+blocks to fill a vector, and the packing costs more than the compares save.
+
+The error list needs the `alloc` feature.
+*/
+
+use alloc::vec::Vec;
+use core::convert::TryInto;
+
+use crate::elparse::{eight_digits_value, float_from_man_exp10, parse_float_bytes, ManExp10};
+use crate::error::ParseError;
+use crate::float::RawFloat;
+
+/// Inputs longer than this are always parsed one at a time
+const MAX_PLAIN_LEN: usize = 32;
+
+/// Parses each of `inputs` into the same index of `out`, with the grammar of
+/// `parse_float_bytes`. Returns the index and error of every input that is
+/// not a float, in order; those entries of `out` are left unchanged.
+///
+/// Panics if `inputs` and `out` have different lengths.
+pub fn parse_many<F: RawFloat>(inputs: &[&[u8]], out: &mut [F]) -> Vec<(usize, ParseError)> {
+    assert_eq!(inputs.len(), out.len(), "parse_many needs one output per input");
+    let mut errors = Vec::new();
+    for (i, (input, out)) in inputs.iter().zip(out.iter_mut()).enumerate() {
+        let value = match Classes::of(input) {
+            Some(classes) => plain_value(input, classes).map_or_else(|| parse_float_bytes(input), Ok),
+            None => parse_float_bytes(input),
+        };
+        match value {
+            Ok(value) => *out = value,
+            Err(e) => errors.push((i, e)),
+        }
+    }
+    errors
+}
+
+/// Bit masks over the bytes of one input: bit i is set if byte i is in the
+/// class.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct Classes {
+    digits: u32,
+    points: u32,
+    exps: u32,
+    signs: u32,
+}
+
+impl Classes {
+    /// The classes of the bytes of an input, or None if it is empty or
+    /// longer than MAX_PLAIN_LEN.
+    // Inlined into the generic parse_many in the caller's crate
+    #[inline]
+    fn of(input: &[u8]) -> Option<Classes> {
+        if input.is_empty() || input.len() > MAX_PLAIN_LEN {
+            return None;
+        }
+        let mut classes = Classes::default();
+        for (i, &c) in input.iter().enumerate() {
+            match c {
+                b'0'..=b'9' => classes.digits |= 1 << i,
+                b'.' => classes.points |= 1 << i,
+                b'e' | b'E' => classes.exps |= 1 << i,
+                b'+' | b'-' => classes.signs |= 1 << i,
+                _ => {}
+            }
+        }
+        Some(classes)
+    }
+}
+
+/// The value of a plain input, given its classes, or None if the input is
+/// not plain or the fast paths cannot round it.
+fn plain_value<F: RawFloat>(input: &[u8], classes: Classes) -> Option<F> {
+    let Classes { digits, points, exps, signs } = classes;
+    let len = input.len();
+    let all = ((1u64 << len) - 1) as u32;
+    if digits | points | exps | signs != all {
+        return None;
+    }
+    // At most one point, then at most one exponent marker, and signs only at
+    // the start of the mantissa and of the exponent
+    if points.count_ones() > 1 || exps.count_ones() > 1 || signs & !(1 | (exps << 1)) != 0 {
+        return None;
+    }
+    let exp_at = if exps == 0 { len } else { exps.trailing_zeros() as usize };
+    let mantissa_mask = ((1u64 << exp_at) - 1) as u32;
+    if points & !mantissa_mask != 0 || digits & mantissa_mask == 0 || (exps != 0 && digits & !mantissa_mask == 0) {
+        return None;
+    }
+    // Longer mantissas need the truncation handling of the full parser
+    if (digits & mantissa_mask).count_ones() > 19 {
+        return None;
+    }
+
+    // So the mantissa is a sign, a run of integer digits, and a point and a
+    // run of fraction digits, either of which may be empty
+    let sign_len = (signs & 1) as usize;
+    let point_at = if points == 0 { exp_at } else { points.trailing_zeros() as usize };
+    let fraction = &input[(point_at + 1).min(exp_at)..exp_at];
+    // At most nineteen digits in all, so this cannot overflow
+    let man = digits_value(&input[sign_len..point_at]) * 10u64.pow(fraction.len() as u32) + digits_value(fraction);
+
+    let mut exp10 = -(fraction.len() as i64);
+    if exps != 0 {
+        let exp_sign_len = usize::from(signs & (exps << 1) != 0);
+        let exp_digits = &input[exp_at + 1 + exp_sign_len..];
+        if exp_digits.len() > 19 {
+            return None;
+        }
+        // Anything this large is already zero or infinite
+        let exp = digits_value(exp_digits).min(1 << 20) as i64;
+        exp10 += if input[exp_at + 1] == b'-' { -exp } else { exp };
+    }
+    // Likewise anything outside the i16 range
+    let e10 = exp10.clamp(i16::MIN.into(), i16::MAX.into()) as i16;
+    float_from_man_exp10(ManExp10 { neg: input[0] == b'-', man, e10, truncated: false })
+}
+
+/// The value of a run of at most nineteen ASCII digits, eight at a time
+fn digits_value(digits: &[u8]) -> u64 {
+    let mut chunks = digits.chunks_exact(8);
+    let mut value = 0;
+    for chunk in &mut chunks {
+        value = value * 100_000_000 + eight_digits_value(u64::from_le_bytes(chunk.try_into().unwrap()));
+    }
+    // Leading zeros make up the rest of a word
+    let rest = chunks.remainder();
+    if !rest.is_empty() {
+        let mut chunk = [b'0'; 8];
+        chunk[8 - rest.len()..].copy_from_slice(rest);
+        value = value * 10u64.pow(rest.len() as u32) + eight_digits_value(u64::from_le_bytes(chunk));
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_many, plain_value, Classes, MAX_PLAIN_LEN};
+    use crate::elparse::parse_float_bytes;
+    use crate::error::ParseError::*;
+    use crate::elparse::tests::lut_covers_input;
+    use rand::random;
+
+    #[test]
+    fn check_classes(){
+        let classes = Classes::of(b"-1_2.5e+x\xff9").unwrap();
+        assert_eq!(classes, Classes { digits: 0b100_0010_1010, points: 0b1_0000, exps: 0b100_0000, signs: 0b1000_0001 });
+        assert_eq!(Classes::of(b""), None);
+        assert_eq!(Classes::of(&[b'1'; MAX_PLAIN_LEN]).map(|c| c.digits), Some(u32::MAX));
+        assert_eq!(Classes::of(&[b'1'; MAX_PLAIN_LEN + 1]), None);
+    }
+
+    #[test]
+    fn check_plain_inputs(){
+        // The shapes of value in the batch benchmark, and the longest plain input
+        let inputs: [&[u8]; 7] = [b"1234.56", b"-96.1234567", b"123e-5", b"0.104729000000000", b"-1.234567890123456",
+                                  b"+12345678.9012345678e-123", b"123456789012345.6789e+1234567890"];
+        assert_eq!(inputs[6].len(), MAX_PLAIN_LEN);
+        for input in inputs.iter() {
+            let expected = parse_float_bytes::<f64>(input).unwrap();
+            match plain_value::<f64>(input, Classes::of(input).unwrap()) {
+                Some(testout) => assert_eq!(testout, expected, "Parsing {:?}", String::from_utf8_lossy(input)),
+                // Plain, but the narrowed power-of-ten table cannot round it
+                None => assert!(!lut_covers_input::<f64>(input), "{:?} should be plain", String::from_utf8_lossy(input)),
+            }
+        }
+    }
+
+    #[test]
+    fn check_parse_many(){
+        // Errors come in order, whether or not the input took the plain path
+        let inputs: [&[u8]; 8] = [b"1.5", b"-2e-3", b"x", b"1.000000000000000000000000000000000x", b"",
+                                  b"12345678901234567890", b"1e", b"+.25E+2"];
+        let mut out = [7.0f64; 8];
+        let errors = parse_many(&inputs, &mut out);
+        assert_eq!(errors, vec![(2, InvalidDigit(0)), (3, InvalidDigit(35)), (4, Empty), (6, MissingExponentDigits(2))]);
+        assert_eq!(out, [1.5, -2e-3, 7.0, 7.0, 7.0, 12345678901234567890.0, 7.0, 25.0]);
+    }
+
+    #[test]
+    fn check_parse_many_matches_parse_float(){
+        // Plain inputs of every length, and some that only look plain
+        let alphabet = b"00123456789..eE+-_";
+        let mut inputs: Vec<Vec<u8>> = (0..20_000).map(|_| {
+            let len = random::<usize>() % (MAX_PLAIN_LEN + 4);
+            (0..len).map(|_| alphabet[random::<usize>() % alphabet.len()]).collect()
+        }).collect();
+        for _ in 0..20_000 {
+            let x = f64::from_bits(random::<u64>());
+            let e = random::<i16>() % 400;
+            inputs.push(format!("{}", x).into_bytes());
+            inputs.push(format!("{:.*e}", random::<usize>() % 12, x).into_bytes());
+            inputs.push(format!("{}.{}e{}", random::<u32>(), random::<u16>(), e).into_bytes());
+        }
+        let inputs: Vec<&[u8]> = inputs.iter().map(|i| i.as_slice()).collect();
+        let mut out = vec![0.0f64; inputs.len()];
+        let errors = parse_many(&inputs, &mut out);
+        let mut errors = errors.iter().peekable();
+        for (i, input) in inputs.iter().enumerate() {
+            let expected = parse_float_bytes::<f64>(input);
+            let testout = match errors.next_if(|(at, _)| *at == i) {
+                Some((_, e)) => Err(*e),
+                None => Ok(out[i]),
+            };
+            assert_eq!(testout.map(f64::to_bits), expected.map(f64::to_bits), "Parsing {:?}", String::from_utf8_lossy(input));
+        }
+    }
+}
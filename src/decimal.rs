@@ -0,0 +1,603 @@
+/* The slow path for decimal-to-binary conversion, used when the Eisel-Lemire
+fast path cannot decide the result. This is the "high precision decimal"
+algorithm from Wuffs (and the Rust standard library's dec2flt): the decimal
+digits are kept verbatim and repeatedly shifted by powers of two until the
+value lies in [1/2, 1), at which point the binary mantissa can be read off
+with exact round-half-even semantics.
+
+Only the first MAX_DIGITS significant digits are kept. Any non-zero digits
+beyond that only matter for breaking exact ties, and are recorded in the
+`truncated` flag. How many digits that takes depends on the format
+(`RawFloat::MAX_DIGITS`), and is the capacity parameter of `Decimal`, so that
+parsing a double does not pay for the much larger array a quad needs.
+
+Everything but `to_float` is a const fn, for `elparse::parse_f64_const`. So
+the code indexes rather than iterates, and converts with `as`.
+*/
+
+use crate::float::{BiasedFp, RawFloat};
+
+/// The maximum number of digits required to unambiguously round a double, or
+/// any narrower format
+pub const DOUBLE_MAX_DIGITS: usize = 768;
+/// The maximum number of digits required to unambiguously round a quad
+pub const QUAD_MAX_DIGITS: usize = 11564;
+
+/// Parses a decimal with the capacity the format F needs and rounds it to F.
+/// Returns None if the input is not a valid float literal.
+pub fn parse_to_float<F: RawFloat>(input: &[u8]) -> Option<F> {
+    // Array lengths cannot depend on F, so choose among the capacities in use
+    if F::MAX_DIGITS <= DOUBLE_MAX_DIGITS {
+        parse_with_capacity::<F, DOUBLE_MAX_DIGITS>(input)
+    } else {
+        debug_assert!(F::MAX_DIGITS <= QUAD_MAX_DIGITS);
+        parse_with_capacity::<F, QUAD_MAX_DIGITS>(input)
+    }
+}
+
+// Out of line, so that a double parse does not reserve the stack for a quad
+#[inline(never)]
+fn parse_with_capacity<F: RawFloat, const N: usize>(input: &[u8]) -> Option<F> {
+    Decimal::<N>::parse(input).map(Decimal::to_float)
+}
+
+/// A big decimal number: 0.d1 d2 d3 ... * 10 ** decimal_point, keeping up to
+/// N significant digits
+pub struct Decimal<const N: usize> {
+    /// Whether the value is negative
+    pub neg: bool,
+    /// The number of significant digits stored in `digits`
+    pub num_digits: usize,
+    /// Position of the decimal point relative to the first digit
+    pub decimal_point: i32,
+    /// Whether non-zero digits were dropped past MAX_DIGITS
+    pub truncated: bool,
+    /// Decimal digits, most significant first, each in 0..=9
+    pub digits: [u8; N],
+}
+
+impl<const N: usize> Default for Decimal<N> {
+    fn default() -> Self {
+        Decimal::new()
+    }
+}
+
+// Shifts larger than this could overflow the u64 accumulators below.
+const MAX_SHIFT: usize = 60;
+
+// Powers of two to shift by when moving the decimal point by n places:
+// POWERS[n] is the largest shift that is guaranteed not to overshoot.
+const POWERS: [u8; 19] = [0, 3, 6, 9, 13, 16, 19, 23, 26, 29, 33, 36, 39, 43, 46, 49, 53, 56, 59];
+
+const fn get_shift(n: usize) -> usize {
+    if n < POWERS.len() {
+        POWERS[n] as usize
+    } else {
+        MAX_SHIFT
+    }
+}
+
+// The decimal digits of 5 ** s for every s in 1..=MAX_SHIFT, back to back.
+// 5 ** 60 has 42 digits.
+const POW5_DIGITS_LEN: usize = 1308;
+
+// POW5_OFFSETS[s]..POW5_OFFSETS[s + 1] are the digits of 5 ** s.
+static POW5_OFFSETS: [u16; MAX_SHIFT + 2] = pow5_tables().0;
+static POW5_DIGITS: [u8; POW5_DIGITS_LEN] = pow5_tables().1;
+
+/// Builds POW5_OFFSETS and POW5_DIGITS, by repeatedly multiplying a
+/// little-endian digit string by five.
+const fn pow5_tables() -> ([u16; MAX_SHIFT + 2], [u8; POW5_DIGITS_LEN]) {
+    let mut offsets = [0u16; MAX_SHIFT + 2];
+    let mut digits = [0u8; POW5_DIGITS_LEN];
+    let mut pow5 = [0u8; 42];
+    pow5[0] = 1;
+    let mut len = 1;
+    let mut end = 0;
+    let mut s = 1;
+    while s <= MAX_SHIFT {
+        let mut carry = 0;
+        let mut i = 0;
+        while i < len {
+            let d = pow5[i] * 5 + carry;
+            pow5[i] = d % 10;
+            carry = d / 10;
+            i += 1;
+        }
+        if carry != 0 {
+            pow5[len] = carry;
+            len += 1;
+        }
+        offsets[s] = end as u16;
+        i = 0;
+        while i < len {
+            digits[end + i] = pow5[len - 1 - i];
+            i += 1;
+        }
+        end += len;
+        s += 1;
+    }
+    offsets[MAX_SHIFT + 1] = end as u16;
+    assert!(end == POW5_DIGITS_LEN);
+    (offsets, digits)
+}
+
+impl<const N: usize> Decimal<N> {
+    /// The number of significant digits kept
+    pub const MAX_DIGITS: usize = N;
+    /// The largest decimal exponent we track before saturating to 0 or inf
+    pub const DECIMAL_POINT_RANGE: i32 = 2047;
+
+    /// Zero
+    pub const fn new() -> Decimal<N> {
+        Decimal {
+            neg: false,
+            num_digits: 0,
+            decimal_point: 0,
+            truncated: false,
+            digits: [0; N],
+        }
+    }
+
+    const fn push_digit(&mut self, d: u8) {
+        if self.num_digits < Self::MAX_DIGITS {
+            self.digits[self.num_digits] = d;
+            self.num_digits += 1;
+        } else if d != 0 {
+            self.truncated = true;
+        }
+    }
+
+    /// Removes trailing zeros, which carry no information.
+    const fn trim(&mut self) {
+        while self.num_digits != 0 && self.digits[self.num_digits - 1] == 0 {
+            self.num_digits -= 1;
+        }
+    }
+
+    /// Parses a decimal in the same syntax as `elparse::parse_float`.
+    /// Returns None if the input is not a valid float literal.
+    pub const fn parse(input: &[u8]) -> Option<Decimal<N>> {
+        let mut d = Decimal::new();
+        let mut i = 0;
+
+        if i < input.len() && (input[i] == b'-' || input[i] == b'+') {
+            d.neg = input[i] == b'-';
+            i += 1;
+        }
+
+        let mut decimal_seen = false;
+        let mut any_digits = false;
+        let mut has_exponent = false;
+        while i < input.len() {
+            let c = input[i];
+            i += 1;
+            match c {
+                b'_' => {}
+                b'.' => {
+                    if decimal_seen {
+                        return None;
+                    }
+                    decimal_seen = true;
+                }
+                b'e' | b'E' => {
+                    has_exponent = true;
+                    break;
+                }
+                b'0'..=b'9' => {
+                    any_digits = true;
+                    let digit = c - b'0';
+                    if d.num_digits == 0 && !d.truncated && digit == 0 {
+                        // Leading zeros only move the decimal point
+                        if decimal_seen {
+                            d.decimal_point -= 1;
+                        }
+                        continue;
+                    }
+                    d.push_digit(digit);
+                    if !decimal_seen {
+                        d.decimal_point += 1;
+                    }
+                }
+                _ => return None,
+            }
+        }
+        if !any_digits {
+            return None;
+        }
+
+        if has_exponent {
+            let mut exp_neg = false;
+            if i < input.len() && (input[i] == b'+' || input[i] == b'-') {
+                exp_neg = input[i] == b'-';
+                i += 1;
+            }
+            let mut exp10 = 0i32;
+            let mut any_exp_digits = false;
+            while i < input.len() {
+                let c = input[i];
+                i += 1;
+                match c {
+                    b'_' => {}
+                    b'0'..=b'9' => {
+                        any_exp_digits = true;
+                        // Saturate: anything this large is already 0 or inf
+                        if exp10 < 0x10000 {
+                            exp10 = 10 * exp10 + (c - b'0') as i32;
+                        }
+                    }
+                    _ => return None,
+                }
+            }
+            if !any_exp_digits {
+                return None;
+            }
+            d.decimal_point += if exp_neg { -exp10 } else { exp10 };
+        }
+
+        d.trim();
+        if d.num_digits == 0 {
+            d.decimal_point = 0;
+        }
+        Some(d)
+    }
+
+    /// The number of leading digits that multiplying by 2 ** shift adds.
+    /// Since 0.P * 2 ** shift = 10 ** (shift - len(P)) for P the digits of
+    /// 5 ** shift, that is one fewer when our digits compare below P.
+    const fn left_shift_new_digits(&self, shift: usize) -> usize {
+        let start = POW5_OFFSETS[shift] as usize;
+        let end = POW5_OFFSETS[shift + 1] as usize;
+        let new_digits = shift - (end - start) + 1;
+        let mut i = 0;
+        while start + i < end {
+            if i >= self.num_digits {
+                return new_digits - 1;
+            }
+            let p = POW5_DIGITS[start + i];
+            if self.digits[i] != p {
+                return if self.digits[i] < p { new_digits - 1 } else { new_digits };
+            }
+            i += 1;
+        }
+        new_digits
+    }
+
+    /// Multiplies the value by 2 ** shift, for shift <= MAX_SHIFT.
+    pub const fn left_shift(&mut self, shift: usize) {
+        debug_assert!(shift <= MAX_SHIFT);
+        if self.num_digits == 0 {
+            return;
+        }
+
+        // Work from the least significant digit up, writing each result
+        // digit new_digits places further along, so no digit is overwritten
+        // before it is read.
+        let new_digits = self.left_shift_new_digits(shift);
+        let mut read_index = self.num_digits;
+        let mut write_index = self.num_digits + new_digits;
+        let mut n = 0u64;
+        while read_index > 0 {
+            read_index -= 1;
+            write_index -= 1;
+            n += (self.digits[read_index] as u64) << shift;
+            self.store_digit(write_index, (n % 10) as u8);
+            n /= 10;
+        }
+        while n > 0 {
+            write_index -= 1;
+            self.store_digit(write_index, (n % 10) as u8);
+            n /= 10;
+        }
+        debug_assert!(write_index == 0);
+
+        self.num_digits += new_digits;
+        if self.num_digits > Self::MAX_DIGITS {
+            self.num_digits = Self::MAX_DIGITS;
+        }
+        self.decimal_point += new_digits as i32;
+        self.trim();
+    }
+
+    /// Stores a shifted digit, or notes that it was dropped.
+    const fn store_digit(&mut self, index: usize, d: u8) {
+        if index < Self::MAX_DIGITS {
+            self.digits[index] = d;
+        } else if d != 0 {
+            self.truncated = true;
+        }
+    }
+
+    /// Divides the value by 2 ** shift, for shift <= MAX_SHIFT.
+    pub const fn right_shift(&mut self, shift: usize) {
+        debug_assert!(shift <= MAX_SHIFT);
+        let mut read_index = 0;
+        let mut write_index = 0;
+        let mut n = 0u64;
+
+        // Read digits until the accumulator holds at least one output digit
+        while (n >> shift) == 0 {
+            if read_index < self.num_digits {
+                n = 10 * n + self.digits[read_index] as u64;
+                read_index += 1;
+            } else if n == 0 {
+                return;
+            } else {
+                while (n >> shift) == 0 {
+                    n *= 10;
+                    read_index += 1;
+                }
+                break;
+            }
+        }
+
+        // read_index is at most MAX_DIGITS + 19
+        self.decimal_point -= read_index as i32 - 1;
+        if self.decimal_point < -Self::DECIMAL_POINT_RANGE {
+            // Too small to matter: flush to zero
+            self.num_digits = 0;
+            self.decimal_point = 0;
+            self.truncated = false;
+            return;
+        }
+
+        let mask = (1u64 << shift) - 1;
+        while read_index < self.num_digits {
+            let new_digit = (n >> shift) as u8;
+            n = 10 * (n & mask) + self.digits[read_index] as u64;
+            read_index += 1;
+            self.digits[write_index] = new_digit;
+            write_index += 1;
+        }
+        while n > 0 {
+            let new_digit = (n >> shift) as u8;
+            n = 10 * (n & mask);
+            if write_index < Self::MAX_DIGITS {
+                self.digits[write_index] = new_digit;
+                write_index += 1;
+            } else if new_digit > 0 {
+                self.truncated = true;
+            }
+        }
+        self.num_digits = write_index;
+        self.trim();
+    }
+
+    /// Rounds the value to the nearest integer, ties to even. Saturates at
+    /// u128::MAX if the integer part has more than 38 digits.
+    pub const fn round(&self) -> u128 {
+        if self.num_digits == 0 || self.decimal_point < 0 {
+            return 0;
+        } else if self.decimal_point > 38 {
+            return u128::MAX;
+        }
+
+        let dp = self.decimal_point as usize;
+        let mut n = 0u128;
+        let mut i = 0;
+        while i < dp {
+            n *= 10;
+            if i < self.num_digits {
+                n += self.digits[i] as u128;
+            }
+            i += 1;
+        }
+
+        let mut round_up = false;
+        if dp < self.num_digits {
+            round_up = self.digits[dp] >= 5;
+            if self.digits[dp] == 5 && dp + 1 == self.num_digits {
+                // Exactly half: round to even unless we dropped digits
+                round_up = self.truncated || (dp != 0 && self.digits[dp - 1] & 1 != 0);
+            }
+        }
+        if round_up {
+            n += 1;
+        }
+        n
+    }
+
+    /// Converts the decimal to the closest float of format F, ties to even.
+    pub fn to_float<F: RawFloat>(mut self) -> F {
+        let fp = self.compute_biased_fp::<F>();
+        F::from_biased_fp(self.neg, fp)
+    }
+
+    /// Returns the fields of the closest float of format F. Consumes the
+    /// digits in the process.
+    pub(crate) const fn compute_biased_fp<F: RawFloat>(&mut self) -> BiasedFp {
+        let zero = BiasedFp::zero();
+        let inf = BiasedFp::infinity::<F>();
+        let mantissa_explicit_bits = F::MANTISSA_EXPLICIT_BITS as usize;
+
+        // Short-circuit if the value can only be a literal 0 or infinity
+        // (0.d1 d2 ... * 10 ** decimal_point is below 10 ** decimal_point.)
+        if self.num_digits == 0 || self.decimal_point < F::SMALLEST_POWER_OF_TEN as i32 + 19 {
+            return zero;
+        } else if self.decimal_point > F::LARGEST_POWER_OF_TEN as i32 + 1 {
+            return inf;
+        }
+
+        // Shift right toward (1/2 ... 1]
+        let mut exp2 = 0i32;
+        while self.decimal_point > 0 {
+            let shift = get_shift(self.decimal_point as usize);
+            self.right_shift(shift);
+            if self.decimal_point < -Self::DECIMAL_POINT_RANGE {
+                return zero;
+            }
+            exp2 += shift as i32;
+        }
+
+        // Shift left toward (1/2 ... 1]
+        while self.decimal_point <= 0 {
+            let shift = if self.decimal_point == 0 {
+                match self.digits[0] {
+                    5..=9 => break,
+                    0 | 1 => 2,
+                    _ => 1,
+                }
+            } else {
+                get_shift(-self.decimal_point as usize)
+            };
+            self.left_shift(shift);
+            if self.decimal_point > Self::DECIMAL_POINT_RANGE {
+                return inf;
+            }
+            exp2 -= shift as i32;
+        }
+
+        // We are now in [1/2 ... 1) but the binary format uses [1 ... 2)
+        exp2 -= 1;
+        while F::MINIMUM_EXPONENT + 1 > exp2 {
+            let n = (F::MINIMUM_EXPONENT + 1 - exp2) as usize;
+            let n = if n < MAX_SHIFT { n } else { MAX_SHIFT };
+            self.right_shift(n);
+            exp2 += n as i32;
+        }
+        if exp2 - F::MINIMUM_EXPONENT >= F::INFINITE_POWER {
+            return inf;
+        }
+
+        // Shift the hidden bit up to the units place and round
+        let mut remaining = mantissa_explicit_bits + 1;
+        while remaining > 0 {
+            let shift = if remaining < MAX_SHIFT { remaining } else { MAX_SHIFT };
+            self.left_shift(shift);
+            remaining -= shift;
+        }
+        let mut mantissa = self.round();
+        if mantissa >= (1 << (mantissa_explicit_bits + 1)) {
+            // Rounding up overflowed into the carry bit: renormalize
+            self.right_shift(1);
+            exp2 += 1;
+            mantissa = self.round();
+            if exp2 - F::MINIMUM_EXPONENT >= F::INFINITE_POWER {
+                return inf;
+            }
+        }
+
+        let mut power2 = exp2 - F::MINIMUM_EXPONENT;
+        if mantissa < (1 << mantissa_explicit_bits) {
+            // No hidden bit: the result is subnormal
+            power2 -= 1;
+        }
+        mantissa &= (1 << mantissa_explicit_bits) - 1;
+        BiasedFp { f: mantissa, e: power2 }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::DOUBLE_MAX_DIGITS;
+    use num_bigint::BigUint;
+    use rand::random;
+
+    type Decimal = super::Decimal<DOUBLE_MAX_DIGITS>;
+
+    fn check_against_std(input: &str) {
+        let expected: f64 = input.parse().unwrap();
+        let testout: f64 = Decimal::parse(input.as_bytes()).expect("Decimal should parse").to_float();
+        assert_eq!(testout.to_bits(), expected.to_bits(), "Parsing {} should have given {:e} but got {:e}", input, expected, testout);
+    }
+
+    #[test]
+    fn check_parse_decimal(){
+        let d = Decimal::parse(b"-001_234.5600e3").unwrap();
+        assert!(d.neg);
+        assert_eq!(&d.digits[..d.num_digits], &[1, 2, 3, 4, 5, 6]);
+        assert_eq!(d.decimal_point, 7);
+
+        let d = Decimal::parse(b"0.000120").unwrap();
+        assert_eq!(&d.digits[..d.num_digits], &[1, 2]);
+        assert_eq!(d.decimal_point, -3);
+
+        let invalid = ["", "-", ".", "e5", "1e", "1e+", "1.2.3", "1x", "1e5.0"];
+        for i in invalid.iter() {
+            assert!(Decimal::parse(i.as_bytes()).is_none(), "{} should not parse", i);
+        }
+    }
+
+    #[test]
+    fn check_left_shift(){
+        // Digit strings that start just below, at and above 5 ** shift
+        for _ in 0..10_000 {
+            let shift = 1 + random::<usize>() % super::MAX_SHIFT;
+            let pow5 = BigUint::from(5u8).pow(shift as u32).to_string();
+            let ndigits = 1 + random::<usize>() % 60;
+            let mut digits: String = (0..ndigits).map(|_| char::from(b'0' + random::<u8>() % 10)).collect();
+            if random::<bool>() {
+                let keep = random::<usize>() % (pow5.len() + 1);
+                digits = format!("{}{}", &pow5[..keep], digits);
+            }
+            let input = format!("1{}", digits);
+            let mut d = Decimal::parse(input.as_bytes()).unwrap();
+            d.left_shift(shift);
+
+            let shifted = (input.parse::<BigUint>().unwrap() << shift).to_string();
+            let testout: String = d.digits[..d.num_digits].iter().map(|&c| char::from(b'0' + c)).collect();
+            assert_eq!(testout, shifted.trim_end_matches('0'), "Shifting {} left by {}", input, shift);
+            assert_eq!(d.decimal_point as usize, shifted.len(), "Shifting {} left by {}", input, shift);
+        }
+    }
+
+    #[test]
+    fn check_halfway_cases(){
+        let inputs = [
+            "9007199254740993",
+            "9007199254740993.0000000000000000000000001",
+            "9007199254740995",
+            "1.00000000000000011102230246251565404236316680908203125",
+            "1.00000000000000011102230246251565404236316680908203124",
+            "1.00000000000000011102230246251565404236316680908203126",
+            "2.4703282292062327208828439643411068618252990130716238221279284125033775363510437593264991818081799618989828234772285886546332835517796989819938739800539093906315035659515570226392290858392449105184435931802849936536152500319370457678249219365623669863658480757001585769269903706311928279558551332927834338409351978015531246597263579574622766465272827220056374006485499977096599470454020828166226237857393450736339007967761930577506740176324673600968951340535537458516661134223766678604162159680461914467291840300530057530849048765391711386591646239524912623653881879636239373280423891018672348497668235089863388587925628302755995657524455507255189313690836254779186948667994968324049705821028513185451396213837722826145437693412532098591327667236328125e-324",
+            "2.4703282292062327208828439643411068618252990130716238221279284125033775363510437593264991818081799618989828234772285886546332835517796989819938739800539093906315035659515570226392290858392449105184435931802849936536152500319370457678249219365623669863658480757001585769269903706311928279558551332927834338409351978015531246597263579574622766465272827220056374006485499977096599470454020828166226237857393450736339007967761930577506740176324673600968951340535537458516661134223766678604162159680461914467291840300530057530849048765391711386591646239524912623653881879636239373280423891018672348497668235089863388587925628302755995657524455507255189313690836254779186948667994968324049705821028513185451396213837722826145437693412532098591327667236328125001e-324",
+        ];
+        for i in inputs.iter() {
+            check_against_std(i);
+        }
+    }
+
+    #[test]
+    fn check_extreme_exponents(){
+        let inputs = ["1e-400", "1e400", "4.9e-324", "2.4e-324", "2.5e-324", "2.2250738585072011e-308",
+                      "1.7976931348623157e308", "1.7976931348623158e308", "1.7976931348623159e308",
+                      "0e999999999", "123456789e-99999", "0.000001e-330", "-1e309"];
+        for i in inputs.iter() {
+            check_against_std(i);
+        }
+    }
+
+    #[test]
+    fn check_random_long_mantissas(){
+        for _ in 0..10_000 {
+            let ndigits = 20 + random::<usize>() % 40;
+            let digits: String = (0..ndigits).map(|_| char::from(b'0' + random::<u8>() % 10)).collect();
+            let e10 = random::<i16>() % 350;
+            check_against_std(&format!("{}e{}", digits, e10));
+            check_against_std(&format!("0.{}e{}", digits, e10));
+        }
+    }
+
+    #[test]
+    fn check_random_f32_long_mantissas(){
+        for _ in 0..10_000 {
+            let ndigits = 10 + random::<usize>() % 40;
+            let digits: String = (0..ndigits).map(|_| char::from(b'0' + random::<u8>() % 10)).collect();
+            let input = format!("0.{}e{}", digits, random::<i8>() % 50);
+            let expected: f32 = input.parse().unwrap();
+            let testout: f32 = Decimal::parse(input.as_bytes()).unwrap().to_float();
+            assert_eq!(testout.to_bits(), expected.to_bits(), "Parsing {} should have given {:e} but got {:e}", input, expected, testout);
+        }
+    }
+
+    #[test]
+    fn check_random_floats(){
+        for _ in 0..10_000 {
+            let f = f64::from_bits(random::<u64>());
+            if f.is_finite() {
+                check_against_std(&format!("{:e}", f));
+            }
+        }
+    }
+}
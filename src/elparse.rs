@@ -1,227 +1,1040 @@
-use std::convert::{TryFrom, TryInto};
+use core::ops::Range;
+#[cfg(feature = "stats")]
+use core::sync::atomic::{AtomicUsize, Ordering};
 
-use crate::lookups::{self, lut_e10_min, lut_e10_max, get_m64, get_narrowbiased_e2};
+use crate::decimal::{self, Decimal};
+use crate::error::ParseError;
+use crate::grammar::{Format, Grammar};
+use crate::float::{is_narrower, widen, BiasedFp, RawFloat};
+use crate::lookups::{get_m64, get_m128_hi, get_m128_lo, get_widebiased_e2};
 
-pub fn parse_float(x: &str) -> Result<f64, std::num::ParseFloatError> {
+// lookups::get_widebiased_e2 bakes in the f64 exponent bias; we rebias its
+// result for the target format.
+const F64_EXPONENT_BIAS: i32 = 1023;
+
+/// How many parses have been answered by Clinger's fast path.
+#[cfg(feature = "stats")]
+static CLINGER_FAST_PATH_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns the number of parses, across all threads, that were answered by
+/// Clinger's fast path without consulting the power-of-ten table.
+#[cfg(feature = "stats")]
+pub fn clinger_fast_path_count() -> usize {
+    CLINGER_FAST_PATH_COUNT.load(Ordering::Relaxed)
+}
+
+pub fn parse_float<F: RawFloat>(x: &str) -> Result<F, ParseError> {
+    parse_float_with_fallback(x.as_bytes())
+}
+
+/// Parses a float from raw bytes, which need not be valid UTF-8. The grammar
+/// is the same as for `parse_float`, so any non-ASCII byte is invalid.
+pub fn parse_float_bytes<F: RawFloat>(x: &[u8]) -> Result<F, ParseError> {
     parse_float_with_fallback(x)
 }
 
-fn parse_float_with_fallback(x: &str) -> Result<f64, std::num::ParseFloatError> {
-    let z = parse_float_internal(x);
-    match z {
-        Some(f) => Ok(f),
-        None => x.parse(),
+/// Parses a float from raw bytes, in the given grammar.
+pub fn parse_float_with_grammar<F: RawFloat>(x: &[u8], grammar: Grammar) -> Result<F, ParseError> {
+    parse_float_with_format(x, &grammar.format())
+}
+
+/// Parses a float from raw bytes, following the rules of `fmt`.
+pub fn parse_float_with_format<F: RawFloat>(x: &[u8], fmt: &Format) -> Result<F, ParseError> {
+    let literal = parse_literal(x, false, fmt)?;
+    Ok(literal.value(x))
+}
+
+fn parse_float_with_fallback<F: RawFloat>(x: &[u8]) -> Result<F, ParseError> {
+    parse_float_with_grammar(x, Grammar::Loose)
+}
+
+/// Parses the longest prefix of `x` that is a float, like C's `strtod`.
+/// Returns the value and the number of bytes consumed: the next byte, if any,
+/// cannot extend the number. Fails only if no prefix is a float.
+pub fn parse_float_partial<F: RawFloat>(x: &[u8]) -> Result<(F, usize), ParseError> {
+    parse_float_partial_with_grammar(x, Grammar::Loose)
+}
+
+/// Like `parse_float_partial`, in the given grammar.
+pub fn parse_float_partial_with_grammar<F: RawFloat>(x: &[u8], grammar: Grammar) -> Result<(F, usize), ParseError> {
+    parse_float_partial_with_format(x, &grammar.format())
+}
+
+/// Like `parse_float_partial`, following the rules of `fmt`. Leading
+/// whitespace, if the format allows it, counts as consumed; trailing
+/// whitespace does not.
+pub fn parse_float_partial_with_format<F: RawFloat>(x: &[u8], fmt: &Format) -> Result<(F, usize), ParseError> {
+    let literal = parse_literal(x, true, fmt)?;
+    Ok((literal.value(x), literal.len))
+}
+
+/// Parses a float at compile time, as `parse_float` would, from a decimal
+/// number such as `-1_000.5e-3`: `parse_float`'s grammar without suffixes,
+/// infinities or NaNs. Panics on any other input, which in a const context
+/// is a compile error.
+///
+/// ```
+/// use eisel_lemire::elparse::parse_f64_const;
+/// const G: f64 = parse_f64_const("6.67430e-11");
+/// assert_eq!(G, 6.67430e-11);
+/// ```
+///
+/// ```compile_fail
+/// use eisel_lemire::elparse::parse_f64_const;
+/// const G: f64 = parse_f64_const("6.67430e");
+/// ```
+pub const fn parse_f64_const(x: &str) -> f64 {
+    let mut decimal = match Decimal::<{ <f64 as RawFloat>::MAX_DIGITS }>::parse(x.as_bytes()) {
+        Some(decimal) => decimal,
+        None => panic!("parse_f64_const: not a decimal float"),
+    };
+
+    // The leading digits give the mantissa for Eisel-Lemire; trailing zeros
+    // are already trimmed, so any further digits are non-zero.
+    let mut man = 0u64;
+    let mut digits = 0;
+    while digits < decimal.num_digits && digits < 19 {
+        man = 10 * man + decimal.digits[digits] as u64;
+        digits += 1;
+    }
+    let truncated = decimal.truncated || decimal.num_digits > 19;
+    // Anything outside the i16 range is already zero or infinite
+    let e10 = decimal.decimal_point - digits as i32;
+    let e10 = if e10 < i16::MIN as i32 { i16::MIN } else if e10 > i16::MAX as i32 { i16::MAX } else { e10 as i16 };
+
+    let fp = if man == 0 {
+        BiasedFp::zero()
+    } else {
+        match eisel_lemire_bracketed::<f64>(man, e10, truncated) {
+            Some(fp) => fp,
+            None => decimal.compute_biased_fp::<f64>(),
+        }
+    };
+    f64::from_bits(fp.to_f64_bits(decimal.neg))
+}
+
+/// The type suffix of a float literal
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Suffix {
+    F32,
+    F64,
+}
+
+/// A value that is not a decimal number, and so does not go through
+/// `float_from_man_exp10`
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Special {
+    Infinity,
+    // A quiet NaN with the given payload
+    Nan(u128),
+    // A hex float: man * 2 ** e2, plus something less than 2 ** e2 if
+    // truncated
+    Hex { man: u128, e2: i32, truncated: bool },
+}
+
+/// A parsed float literal
+#[derive(Debug, PartialEq, Eq)]
+struct Literal {
+    // For special values, only the sign is meaningful
+    parts: ManExp10,
+    special: Option<Special>,
+    // Where the number is in the input, excluding whitespace and any suffix
+    number: Range<usize>,
+    suffix: Option<Suffix>,
+    // The length of the whole literal
+    len: usize,
+}
+
+impl Literal {
+    /// Rounds the literal to F. A suffix naming a narrower type than F means
+    /// rounding to that type, which F then represents exactly.
+    fn value<F: RawFloat>(&self, input: &[u8]) -> F {
+        match self.special {
+            Some(Special::Infinity) => return F::from_biased_fp(self.parts.neg, BiasedFp::infinity::<F>()),
+            Some(Special::Nan(payload)) => return F::from_biased_fp(self.parts.neg, BiasedFp::nan::<F>(payload)),
+            Some(Special::Hex { man, e2, truncated }) => return F::from_biased_fp(self.parts.neg, round_man_exp2::<F>(man, e2, truncated)),
+            None => {}
+        }
+        match self.suffix {
+            Some(Suffix::F32) if is_narrower::<f32, F>() => widen(self.round::<f32>(input)),
+            Some(Suffix::F64) if is_narrower::<f64, F>() => widen(self.round::<f64>(input)),
+            _ => self.round(input),
+        }
+    }
+
+    fn round<F: RawFloat>(&self, input: &[u8]) -> F {
+        float_from_man_exp10(self.parts).unwrap_or_else(|| parse_decimal(&input[self.number.clone()]))
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Default)]
-struct ManExp10 {
-    neg: bool,
-    man: u64,
-    e10: i16,
+/// The slow path, for input that `parse_man_exp10_prefix` has already validated.
+fn parse_decimal<F: RawFloat>(x: &[u8]) -> F {
+    decimal::parse_to_float(x).expect("Decimal::parse accepts everything parse_man_exp10_prefix does")
 }
 
-/// The Eisel-Lemire float-parsing algorithm. If the result is None, the caller
-/// should invoke the fallback algorithm.
+// For `batch`, which locates digits by itself and only needs converting them
+#[cfg(any(feature = "alloc", test))]
+pub(crate) use parse_parts::eight_digits_value;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub(crate) struct ManExp10 {
+    pub neg: bool,
+    pub man: u64,
+    pub e10: i16,
+    // True if the decimal had more significant digits than fit in `man`
+    pub truncated: bool,
+}
+
+/// The fast paths alone, without the fallback. Tests use this to check which
+/// inputs they handle.
 /* We use the syntax for float literals described at
 https://doc.rust-lang.org/stable/reference/tokens.html#floating-point-literals
 */
-fn parse_float_internal(input: &str) -> Option<f64> {
+#[cfg(test)]
+fn parse_float_internal<F: RawFloat>(input: &[u8]) -> Option<F> {
     // Step 1: split string into a mantissa and exponent
-    let ManExp10 { neg, man, e10 } = parse_man_exp10(input)?;
+    float_from_man_exp10(parse_man_exp10(input).ok()?)
+}
+
+/// The Eisel-Lemire float-parsing algorithm, preceded by Clinger's fast path.
+/// If the result is None, the caller should invoke the fallback algorithm in
+/// `crate::decimal`.
+pub(crate) fn float_from_man_exp10<F: RawFloat>(parts: ManExp10) -> Option<F> {
+    let ManExp10 { neg, man, e10, truncated } = parts;
 
     // Check zero mantissa
     if man == 0 {
-        return Some(0.0);
+        return Some(F::from_biased_fp(neg, BiasedFp::zero()));
+    }
+
+    // Small exact inputs such as 12.375 need no table lookup at all
+    if !truncated {
+        if let Some(f) = F::clinger_fast_path(neg, man, e10) {
+            #[cfg(feature = "stats")]
+            CLINGER_FAST_PATH_COUNT.fetch_add(1, Ordering::Relaxed);
+            return Some(f);
+        }
     }
 
-    let m64 = get_m64(e10)?;
-    let narrowbiased_e2 = get_narrowbiased_e2(e10)?;
+    let f = eisel_lemire_bracketed::<F>(man, e10, truncated)?;
+    Some(F::from_biased_fp(neg, f))
+}
+
+// The Eisel-Lemire core below is made of const fns, so that
+// `parse_f64_const` can run it at compile time. Const fns cannot call trait
+// methods, so they convert with `as` (always losslessly, unless noted) where
+// the rest of the crate uses From and TryFrom, and match instead of `?`.
+
+/// `eisel_lemire` for a mantissa that may have been truncated. The true
+/// mantissa then lies strictly between man and man + 1: if both ends of that
+/// interval round to the same float, so does everything in between.
+const fn eisel_lemire_bracketed<F: RawFloat>(man: u64, e10: i16, truncated: bool) -> Option<BiasedFp> {
+    let f = match eisel_lemire::<F>(man, e10) {
+        Some(f) => f,
+        None => return None,
+    };
+    if truncated {
+        match eisel_lemire::<F>(man + 1, e10) {
+            // PartialEq is a trait, so compare the fields
+            Some(g) if g.f == f.f && g.e == f.e => {}
+            _ => return None,
+        }
+    }
+    Some(f)
+}
+
+/// Computes the fields of man * 10 ** e10 in the format F, for a non-zero
+/// mantissa. Returns None if the result cannot be determined from the lookup
+/// table alone.
+const fn eisel_lemire<F: RawFloat>(man: u64, e10: i16) -> Option<BiasedFp> {
+    // Even the largest mantissa is below half of the smallest subnormal
+    if e10 < F::SMALLEST_POWER_OF_TEN {
+        return Some(BiasedFp::zero());
+    }
+    // Even the smallest mantissa is above the largest finite float
+    if e10 > F::LARGEST_POWER_OF_TEN {
+        return Some(BiasedFp::infinity::<F>());
+    }
+
+    let widebiased_e2 = match get_widebiased_e2(e10) {
+        Some(e2) => e2,
+        None => return None,
+    };
 
     // Perform mantissa normalization
-    let nor_man = man << man.leading_zeros();
-    let adje2 = narrowbiased_e2 - i16::try_from(man.leading_zeros()).ok()?;
+    let clz = man.leading_zeros();
+    let nor_man = man << clz;
+    let adje2 = widebiased_e2 as i32 - F64_EXPONENT_BIAS + F::EXPONENT_BIAS - clz as i32;
 
-    let w: u128 = u128::from(nor_man) * u128::from(m64);
-    let wbytes = w.to_le_bytes();
-    let (whi_bytes, wlo_bytes) = wbytes.split_at(std::mem::size_of::<u64>());
-    let whi = u64::from_le_bytes(whi_bytes.try_into().unwrap());
-    let wlo = u64::from_le_bytes(wlo_bytes.try_into().unwrap());
-    unimplemented!()
+    // We need the mantissa bits, the hidden bit, a rounding bit and possibly
+    // a leading zero bit from the top of the product. For formats up to
+    // 61 mantissa bits the high 64 bits of the product are enough.
+    if F::MANTISSA_EXPLICIT_BITS + 3 <= 64 {
+        match product_64::<F>(nor_man, e10) {
+            Some((whi, wlo_zero)) => round_product::<F>(whi as u128, 64, wlo_zero, adje2),
+            None => None,
+        }
+    } else {
+        match product_128::<F>(nor_man, e10) {
+            Some((whi, wlo_zero)) => round_product::<F>(whi, 128, wlo_zero, adje2),
+            None => None,
+        }
+    }
+}
+
+/// Multiplies the normalized mantissa by the 64-bit (and if necessary the
+/// 128-bit) power of ten. Returns the high 64 bits of the product and whether
+/// the bits below them are zero, or None if truncating the power leaves the
+/// high bits undetermined.
+const fn product_64<F: RawFloat>(nor_man: u64, e10: i16) -> Option<(u64, bool)> {
+    // Bits of the high word below the ones we keep
+    let low_bits_mask = (1u64 << (64 - (F::MANTISSA_EXPLICIT_BITS + 3))) - 1;
+
+    let m64 = match get_m64(e10) {
+        Some(m64) => m64,
+        None => return None,
+    };
+    let w: u128 = nor_man as u128 * m64 as u128;
+    let mut whi = (w >> 64) as u64;
+    let mut wlo = w as u64;
+
+    // The true product lies in [w, w + nor_man) because m64 is truncated.
+    // If the bits below the ones we keep are all ones, adding that error
+    // could carry into the result, so refine the product with the low half
+    // of the 128-bit power before deciding.
+    if whi & low_bits_mask == low_bits_mask && wlo.checked_add(nor_man).is_none() {
+        let m128_lo = match get_m128_lo(e10) {
+            Some(m128_lo) => m128_lo,
+            None => return None,
+        };
+        let y: u128 = nor_man as u128 * m128_lo as u128;
+        let yhi = (y >> 64) as u64;
+        let ylo = y as u64;
+
+        let (merged_lo, carry) = wlo.overflowing_add(yhi);
+        let merged_hi = whi + carry as u64;
+
+        // The refined product is still truncated, so the same carry problem
+        // can occur one word further down. Give up if it does.
+        if merged_hi & low_bits_mask == low_bits_mask
+            && merged_lo == u64::MAX
+            && ylo.checked_add(nor_man).is_none()
+        {
+            return None;
+        }
+        whi = merged_hi;
+        wlo = merged_lo;
+    }
+    Some((whi, wlo == 0))
+}
+
+/// Multiplies the normalized mantissa by the full 128-bit power of ten, for
+/// formats whose mantissas do not fit in the high word of a 64-bit product.
+/// Returns the high 128 bits of the 192-bit product and whether the bits
+/// below them are zero, or None if the high bits are undetermined.
+const fn product_128<F: RawFloat>(nor_man: u64, e10: i16) -> Option<(u128, bool)> {
+    // Bits of the high 128 bits below the ones we keep
+    let low_bits_mask = (1u128 << (128 - (F::MANTISSA_EXPLICIT_BITS + 3))) - 1;
+
+    let (m128_hi, m128_lo) = match (get_m128_hi(e10), get_m128_lo(e10)) {
+        (Some(m128_hi), Some(m128_lo)) => (m128_hi, m128_lo),
+        _ => return None,
+    };
+    let x: u128 = nor_man as u128 * m128_hi as u128;
+    let y: u128 = nor_man as u128 * m128_lo as u128;
+    // x <= (2 ** 64 - 1) ** 2, so this cannot overflow
+    let whi = x + (y >> 64);
+    let wlo = y as u64;
+
+    // As in product_64, the true product lies in [w, w + nor_man), and there
+    // are no more table bits to refine it with.
+    if whi & low_bits_mask == low_bits_mask && wlo.checked_add(nor_man).is_none() {
+        return None;
+    }
+    Some((whi, wlo == 0))
+}
+
+/// Rounds the high `width` bits of a product to the format F, given the
+/// biased exponent of the product. `lower_zero` says whether all product bits
+/// below those are zero.
+const fn round_product<F: RawFloat>(whi: u128, width: u32, lower_zero: bool, adje2: i32) -> Option<BiasedFp> {
+    let low_bits = width - (F::MANTISSA_EXPLICIT_BITS + 3);
+
+    // Shift down to the mantissa bits, the hidden bit, and one rounding bit.
+    // The top bit of the product is 0 or 1.
+    let msb = (whi >> (width - 1)) as u32;
+    let ret_e2 = adje2 - (msb == 0) as i32;
+    let (shift, ret_e2) = pin_subnormal((msb + low_bits) as i64, ret_e2 as i64);
+    if shift >= width as i64 {
+        // Below half of the smallest subnormal
+        return Some(BiasedFp::zero());
+    }
+    let shift = shift as u32;
+    let mut ret_man = whi >> shift;
+
+    // Half-way ambiguity: we may be exactly between two floats, or just above.
+    if lower_zero && whi & ((1 << shift) - 1) == 0 && ret_man & 3 == 1 {
+        return None;
+    }
+
+    // Round half-even, dropping the rounding bit
+    ret_man += ret_man & 1;
+    ret_man >>= 1;
+    Some(pack_rounded::<F>(ret_man, ret_e2))
+}
+
+/// Takes the shift that leaves the hidden bit and the explicit mantissa bits
+/// (plus any rounding bits), and the biased exponent of the result, and
+/// adjusts both for a subnormal result.
+const fn pin_subnormal(shift: i64, ret_e2: i64) -> (i64, i64) {
+    if ret_e2 <= 0 {
+        // Subnormal result: pin the exponent at its minimum and give up
+        // mantissa bits instead.
+        (shift + 1 - ret_e2, 1)
+    } else {
+        (shift, ret_e2)
+    }
+}
+
+/// The fields of a float from its rounded mantissa, with the hidden bit in
+/// place unless subnormal, and the biased exponent from `pin_subnormal`.
+const fn pack_rounded<F: RawFloat>(mut ret_man: u128, mut ret_e2: i64) -> BiasedFp {
+    if ret_man >> (F::MANTISSA_EXPLICIT_BITS + 1) > 0 {
+        // Rounding overflowed the mantissa: renormalize
+        ret_man >>= 1;
+        ret_e2 += 1;
+    }
+
+    if ret_e2 >= F::INFINITE_POWER as i64 {
+        return BiasedFp::infinity::<F>();
+    }
+
+    if ret_man >> F::MANTISSA_EXPLICIT_BITS == 0 {
+        // Subnormal: no hidden bit, and a zero exponent field
+        BiasedFp { f: ret_man, e: 0 }
+    } else {
+        let mantissa_mask = (1 << F::MANTISSA_EXPLICIT_BITS) - 1;
+        BiasedFp { f: ret_man & mantissa_mask, e: ret_e2 as i32 }
+    }
+}
+
+/// Rounds man * 2 ** e2 to the format F. If `truncated`, the true mantissa
+/// lies strictly between man and man + 1. Every such value is a dyadic
+/// rational, so unlike the decimal paths this is exact with no table.
+fn round_man_exp2<F: RawFloat>(man: u128, e2: i32, truncated: bool) -> BiasedFp {
+    if man == 0 {
+        // Truncation only drops digits after the first 124 significant bits
+        return BiasedFp::zero();
+    }
+    // Normalize so that man is in [2 ** 127, 2 ** 128). The unbiased exponent
+    // is then e2 + 127; i64 arithmetic cannot overflow from an i32 e2.
+    let lz = man.leading_zeros();
+    let man = man << lz;
+    let ret_e2 = i64::from(e2) - i64::from(lz) + 127 + i64::from(F::EXPONENT_BIAS);
+
+    // Keep the hidden bit and the explicit mantissa bits
+    let shift = i64::from(128 - (F::MANTISSA_EXPLICIT_BITS + 1));
+    let (shift, ret_e2) = pin_subnormal(shift, ret_e2);
+    if shift > 128 {
+        // Below half of the smallest subnormal
+        return BiasedFp::zero();
+    }
+    let shift = shift as u32;
+    let mut ret_man = man.checked_shr(shift).unwrap_or(0);
+    let rem = man & (u128::MAX >> (128 - shift));
+    let half = 1 << (shift - 1);
+
+    // Round half-even, where truncated digits put us above half-way
+    if rem > half || (rem == half && (truncated || ret_man & 1 == 1)) {
+        ret_man += 1;
+    }
+    pack_rounded::<F>(ret_man, ret_e2)
 }
 
 /*
   This is the first-stage parsing algorithm. Most of the quirks of the floating
   point literal format are dealt with in this function, so we'll document some
-  assumptions here. A floating point literal is:
+  assumptions here. In `Grammar::RustLiteral`, a floating point literal is:
 
   - A decimal literal followed by a period character U+002E (.). This is
     optionally followed by another decimal literal, with an optional exponent.
   - A single decimal literal followed by an exponent.
+  - A decimal literal with an `f32` or `f64` suffix.
 
   Exponent can either be "e" or "E". Note that any number of underscores `_` are
-  allowed in `DEC_LITERAL`s.
+  allowed in `DEC_LITERAL`s after the first digit, and anywhere in the exponent
+  as long as it has a digit. A period without digits after it must end the
+  literal. The other grammars in `crate::grammar` change these rules.
 
-  We ignore the floating-point suffix and assume that all literals are to be
-  parsed as f64.
+  The literal may end in an `f32` or `f64` suffix. An `f32` suffix rounds the
+  value to f32 even when the caller asked for a wider type.
+
+  The parser stops at the first character that cannot extend the number, and
+  an `e` only starts an exponent if a digit (after an optional sign) follows
+  it. `parse_man_exp10` then requires that the whole input was consumed.
 
   Note that this parsing inherently deals with certain parts of the man/exp range.
-  In order to avoid having to allocate, we terminate the parsing and return None
-  if there are more than 19 digits in the mantissa or if the exponent saturates
-  an i16.
+  In order to avoid having to allocate, we keep only the first 19 significant
+  digits of the mantissa and flag the result as truncated if any of the rest
+  are non-zero. Exponents saturate at the ends of the i16 range, which is far
+  beyond anything that can produce a finite, non-zero f64.
 */
-fn parse_man_exp10(input: &str) -> Option<ManExp10> {
-    let mut inp_iter = input.chars();
-
-    let neg = parse_parts::parse_leading_sign(&mut inp_iter)?;
-    let (man, man_exp10, has_exp) = parse_parts::parse_mantissa_base10(&mut inp_iter)?;
-    let explicit_exp10 = if has_exp {
-        parse_parts::parse_exp10(&mut inp_iter)?
+fn parse_literal(input: &[u8], partial: bool, fmt: &Format) -> Result<Literal, ParseError> {
+    let (parts, special, number) = parse_man_exp10_prefix(input, partial, fmt)?;
+    let digits = &input[number.clone()];
+    let bare_point = digits.ends_with(b".");
+    let suffix = if fmt.suffixes && special.is_none() && !(bare_point && fmt.bare_point_ends) {
+        parse_parts::parse_suffix(&input[number.end..])
     } else {
-        0i16
+        None
     };
+    let len = number.end + if suffix.is_some() { 3 } else { 0 };
+    if !partial {
+        let mut end = len;
+        if fmt.trailing_whitespace {
+            end += input[end..].iter().take_while(|&&c| parse_parts::is_space(c)).count();
+        }
+        if end < input.len() {
+            return Err(match input[end] {
+                // A point the format does not allow here, rather than a second one
+                b'.' if end == number.end && special.is_none() && !digits.iter().any(|c| matches!(c, b'.' | b'e' | b'E')) => {
+                    ParseError::MissingDigits(end + 1)
+                }
+                c => ParseError::unexpected(c, end, mantissa_has_point(digits, special)),
+            });
+        }
+    }
+    // Without a point, exponent or suffix the number is an integer
+    if fmt.point_or_exponent && special.is_none() && suffix.is_none() && !digits.iter().any(|c| matches!(c, b'.' | b'e' | b'E')) {
+        return Err(ParseError::MissingPointOrExponent(number.end));
+    }
+    Ok(Literal { parts, special, number, suffix, len })
+}
+
+/// Parses a whole input, which must not have a suffix, into a ManExp10.
+#[cfg(test)]
+fn parse_man_exp10(input: &[u8]) -> Result<ManExp10, ParseError> {
+    match parse_literal(input, false, &Grammar::Loose.format())? {
+        Literal { parts, suffix: None, .. } => Ok(parts),
+        Literal { number, special, .. } => {
+            Err(ParseError::unexpected(input[number.end], number.end, mantissa_has_point(&input[number.clone()], special)))
+        }
+    }
+}
+
+/// Whether the mantissa of a number (without the suffix) has a decimal point
+fn mantissa_has_point(digits: &[u8], special: Option<Special>) -> bool {
+    let exp_markers: &[u8] = if matches!(special, Some(Special::Hex { .. })) { b"pP" } else { b"eE" };
+    digits.iter().take_while(|c| !exp_markers.contains(c)).any(|&c| c == b'.')
+}
+
+/// Parses the longest prefix of the input that is a float, returning its
+/// parts or special value and where the number is, after any leading
+/// whitespace. If `partial` is set, an `e` without exponent digits ends the
+/// prefix rather than being an error.
+fn parse_man_exp10_prefix(input: &[u8], partial: bool, fmt: &Format) -> Result<(ManExp10, Option<Special>, Range<usize>), ParseError> {
+    let mut cursor = Cursor::new(input);
+    if fmt.leading_whitespace {
+        while cursor.peek().is_some_and(parse_parts::is_space) {
+            cursor.next();
+        }
+    }
+    let start = cursor.offset();
+
+    let neg = parse_parts::parse_leading_sign(&mut cursor, fmt)?;
+    if let Some(rules) = &fmt.special_values {
+        if let Some(special) = parse_parts::parse_special(&mut cursor, rules) {
+            let parts = ManExp10 { neg, ..ManExp10::default() };
+            return Ok((parts, Some(special), start..cursor.offset()));
+        }
+    }
+    if fmt.hex_floats && parse_parts::starts_hex(&cursor) {
+        cursor.advance(2);
+        let (man, man_exp2, truncated, has_exp) = parse_parts::parse_mantissa_base16(&mut cursor)?;
+        let (explicit_exp2, end) = parse_exponent(&mut cursor, fmt, partial, has_exp)?;
+        let hex = Special::Hex { man, e2: man_exp2.saturating_add(explicit_exp2), truncated };
+        return Ok((ManExp10 { neg, ..ManExp10::default() }, Some(hex), start..end));
+    }
+    let (man, man_exp10, truncated, has_exp) = parse_parts::parse_mantissa_base10(&mut cursor, fmt)?;
+    let (explicit_exp10, end) = parse_exponent(&mut cursor, fmt, partial, has_exp)?;
+
+    // Anything outside the i16 range is already zero or infinite
+    let exp10 = man_exp10.saturating_add(explicit_exp10);
+    let exp10 = exp10.clamp(i16::MIN.into(), i16::MAX.into()) as i16;
+    Ok((ManExp10{
+        neg, man, e10: exp10, truncated
+    }, None, start..end))
+}
 
-    let exp10 = man_exp10 + explicit_exp10;
-    Some(ManExp10{
-        neg, man, e10: exp10
-    })
+/// Parses the exponent, if the mantissa ended with its `e` or `p`, and
+/// returns it along with the end of the number.
+fn parse_exponent(cursor: &mut Cursor, fmt: &Format, partial: bool, has_exp: bool) -> Result<(i32, usize), ParseError> {
+    let end = cursor.offset();
+    if !has_exp {
+        return Ok((0, end));
+    }
+    match parse_parts::parse_exp10(cursor, fmt) {
+        Ok(e) => Ok((e, cursor.offset())),
+        // Without digits, the `e` is not part of the number
+        Err(ParseError::MissingExponentDigits(_)) if partial => Ok((0, end - 1)),
+        Err(e) => Err(e),
+    }
 }
 
+use parse_parts::Cursor;
+
 mod parse_parts {
-use std::str::Chars;
-use std::convert::TryInto;
-
-/// Parses the sign of the number (true for negative), advancing the input
-/// iterator to the appropriate next point. Returns None if the given stream
-/// is unparseable at the current location.
-pub fn parse_leading_sign(inp_iter: &mut Chars) -> Option<bool> {
-    let my_itr = inp_iter.clone();
-    let first_char = *my_itr.peekable().peek()?;
-    if ['+', '-'].contains(&first_char) {
-        let is_neg_sym = first_char == '-';
-        // Advance cur_char to non-sign input. If we get no input, it's not a valid float literal.
-        let c = inp_iter.next().unwrap();
-        assert!(c == '-' || c == '+');
-        Some(is_neg_sym)
+use super::{Special, Suffix};
+use core::convert::TryInto;
+use crate::error::ParseError;
+use crate::grammar::{Format, SpecialValues, Underscores};
+
+/// A byte iterator over the input that knows its offset, for error reporting.
+#[derive(Debug, Clone)]
+pub struct Cursor<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    pub fn new(input: &'a [u8]) -> Cursor<'a> {
+        Cursor { input, pos: 0 }
+    }
+
+    /// The offset of the next byte
+    pub fn offset(&self) -> usize {
+        self.pos
+    }
+
+    pub fn peek(&self) -> Option<u8> {
+        self.input.get(self.pos).copied()
+    }
+
+    /// The byte `n` bytes after the next one
+    pub fn peek_nth(&self, n: usize) -> Option<u8> {
+        self.input.get(self.pos + n).copied()
+    }
+
+    /// The bytes not yet consumed
+    pub fn rest(&self) -> &'a [u8] {
+        &self.input[self.pos..]
+    }
+
+    pub fn advance(&mut self, n: usize) {
+        self.pos = (self.pos + n).min(self.input.len());
+    }
+}
+
+impl<'a> Iterator for Cursor<'a> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        let c = self.peek()?;
+        self.pos += 1;
+        Some(c)
+    }
+}
+
+/// Parses the sign of the number (true for negative), advancing the cursor
+/// past it. Fails on empty input, or on a sign the format does not allow.
+pub fn parse_leading_sign(cursor: &mut Cursor, fmt: &Format) -> Result<bool, ParseError> {
+    let first_char = cursor.peek().ok_or(ParseError::Empty)?;
+    if [b'+', b'-'].contains(&first_char) {
+        if !(if first_char == b'-' { fmt.minus_sign } else { fmt.plus_sign }) {
+            return Err(ParseError::InvalidSign(cursor.offset()));
+        }
+        cursor.next();
+        Ok(first_char == b'-')
     } else {
-        Some(false)
+        Ok(false)
     }
 }
 
 
-/// Returns a (u64, i16, bool) pair such that u64 * 10 ** i16 = mantissa, and boolean is
-/// true if the string has an explicit exponent present.
-/// Returns None if this input is unparseable, or if the mantissa is longer than 19 digits
-/// If exponent boolean is true, inp_iter is placed at the first character
-/// following the first 'e' or 'E' in the string.
-pub fn parse_mantissa_base10(inp_iter: &mut Chars) -> Option<(u64, i16, bool)> {
-    let mut cur_char = inp_iter.next();
+/// Returns a (u64, i32, bool, bool) tuple such that u64 * 10 ** i32 = mantissa.
+/// The first boolean is true if non-zero digits past the first 19 significant
+/// digits were dropped, in which case the true mantissa lies strictly between
+/// u64 and u64 + 1 (times 10 ** i32). The second boolean is true if the string
+/// has an explicit exponent present.
+/// Fails if there are no digits before the first character that cannot
+/// extend the mantissa, and otherwise leaves the cursor at that character.
+/// If exponent boolean is true, that character was an 'e' or 'E' and the
+/// cursor is placed just after it.
+pub fn parse_mantissa_base10(cursor: &mut Cursor, fmt: &Format) -> Result<(u64, i32, bool, bool), ParseError> {
+    scan_mantissa_base10(cursor, fmt, true)
+}
 
+/// `parse_mantissa_base10`, optionally consuming runs of eight digits at a
+/// time with SWAR arithmetic. Both ways give the same result.
+pub fn scan_mantissa_base10(cursor: &mut Cursor, fmt: &Format, swar: bool) -> Result<(u64, i32, bool, bool), ParseError> {
     // Parse the mantissa
     let mut decimal_seen = false;
-    let mut digits = 0i16;
-    let mut digits_pre_decimal = 0i16;
+    let mut any_digits = false;
+    // Digits since the start or the decimal point
+    let mut part_digits = false;
+    let mut prev_digit = false;
+    // The integer digits so far are a single zero
+    let mut zero_integer = false;
+    let mut sig_digits = 0;
+    let mut exp10 = 0i32;
     let mut mantissa = 0u64;  // Must be 64bit to handle at least 19 decimals
+    let mut truncated = false;
     let mut has_exponent = false;
 
-    while digits < 20 && cur_char.is_some() {
-      let c = cur_char.unwrap();
+    while let Some(c) = cursor.peek() {
+      // At the start of a run of digits, take eight at a time while they
+      // either all fit in the mantissa or are all dropped, and none can be a
+      // disallowed leading zero. The digit arm below takes the rest.
+      if swar && !prev_digit && cursor.rest().len() >= 8
+        && (fmt.leading_zeros || decimal_seen || (any_digits && !zero_integer))
+      {
+        while let Some(v) = read_eight_digits(cursor.rest()) {
+          if sig_digits == 19 {
+            drop_digits(v != 0, 8, decimal_seen, &mut truncated, &mut exp10);
+          } else if sig_digits <= 11 {
+            // mantissa < 10 ** 11, so this cannot overflow
+            mantissa = mantissa * 100_000_000 + v;
+            // Leading zeros are not significant
+            sig_digits = mantissa.checked_ilog10().map_or(0, |l| l + 1);
+            if decimal_seen {
+              exp10 = exp10.saturating_sub(8);
+            }
+          } else {
+            break;
+          }
+          zero_integer = false;
+          any_digits = true;
+          part_digits = true;
+          prev_digit = true;
+          cursor.advance(8);
+        }
+        if prev_digit {
+          continue;
+        }
+      }
       match c {
-        '_' => { 
+        b'0'..=b'9' | b'_' if zero_integer && !decimal_seen && !fmt.leading_zeros => {
+          break;  // A leading zero must be the whole integer part
+        }
+        b'_' if underscore_allowed(cursor, fmt.underscores, part_digits, prev_digit) => {
           // Do nothing: we pretend this character doesn't exist
+          prev_digit = false;
         },
-        '.' => {
-          if decimal_seen {
-            return None;  // Seeing two decimal in a floating point
+        b'.' => {
+          if decimal_seen || (fmt.integer_digits && !any_digits) {
+            break;  // A second decimal point, or a leading one, ends the number
+          }
+          if fmt.fraction_digits && !cursor.peek_nth(1).is_some_and(|c| c.is_ascii_digit()) {
+            break;  // The point needs digits after it to be part of the number
           }
           decimal_seen = true;
+          part_digits = false;
+          prev_digit = false;
+          cursor.next();
+          if fmt.bare_point_ends && !cursor.peek().is_some_and(|c| c.is_ascii_digit()) {
+            break;  // "1." must be the whole number
+          }
+          continue;
         }
-        'e' | 'E' => {
+        b'e' | b'E' if any_digits => {
           // Mantissa is done: this is the start of the exponent
           has_exponent = true;
+          cursor.next();
           break;
         }
-        '0'..='9' => {
-          mantissa *= 10;
-          let d: u64 = c.to_digit(10)?.into();
-          mantissa += d;
-
-          digits += 1;
-          if !decimal_seen{
-              digits_pre_decimal += 1;
+        b'0'..=b'9' => {
+          let d = u64::from(c - b'0');
+          zero_integer = !any_digits && !decimal_seen && d == 0;
+          any_digits = true;
+          part_digits = true;
+          prev_digit = true;
+          if sig_digits < 19 {
+            mantissa = mantissa * 10 + d;
+            // Leading zeros are not significant
+            if mantissa != 0 {
+              sig_digits += 1;
+            }
+            if decimal_seen {
+              exp10 = exp10.saturating_sub(1);
+            }
+          } else {
+            drop_digits(d != 0, 1, decimal_seen, &mut truncated, &mut exp10);
           }
         }
         _ => {
-          return None; // Non-decimal digit encountered
+          break; // Non-decimal digit (or non-ASCII byte) ends the number
         }
       };
-      cur_char = inp_iter.next();
+      cursor.next();
     }
 
-    // mantissa overflow--revert to fallback
-    if digits >= 20 {
-        return None
+    // A mantissa needs at least one digit: "-", "." and "e5" are not floats
+    if !any_digits {
+        return Err(match cursor.peek() {
+            Some(b'.') if decimal_seen => ParseError::MultipleDecimalPoints(cursor.offset()),
+            None | Some(b'.') | Some(b'e') | Some(b'E') => ParseError::MissingDigits(cursor.offset()),
+            Some(c) => ParseError::unexpected(c, cursor.offset(), decimal_seen),
+        });
     }
 
-    Some((mantissa, digits_pre_decimal - digits, has_exponent))
+    Ok((mantissa, exp10, truncated, has_exponent))
 }
 
-/// Parses an exponent starting AFTER `e` or `E`.
-pub fn parse_exp10(inp_iter: &mut Chars) -> Option<i16> {
-    let mut neg = false;
-    let mut exp10 = 0i64;
+/// True if the cursor is at the `0x` of a hex float: one with a hex digit
+/// after the `0x` or after a point following it. Otherwise the `0` alone is
+/// the number, as with `strtod`.
+pub fn starts_hex(cursor: &Cursor) -> bool {
+    match cursor.rest() {
+        [b'0', b'x' | b'X', b'.', c, ..] | [b'0', b'x' | b'X', c, ..] => c.is_ascii_hexdigit(),
+        _ => false,
+    }
+}
+
+/// The hex counterpart of `parse_mantissa_base10`, starting after the `0x`:
+/// returns a (u128, i32, bool, bool) tuple such that u128 * 2 ** i32 is the
+/// mantissa. Digits past the first 124 significant bits are dropped, which
+/// is more than any format needs for rounding; the booleans mean the same as
+/// for decimal, and the exponent starts with `p` or `P`.
+pub fn parse_mantissa_base16(cursor: &mut Cursor) -> Result<(u128, i32, bool, bool), ParseError> {
+    let mut decimal_seen = false;
+    let mut any_digits = false;
+    let mut exp2 = 0i32;
+    let mut mantissa = 0u128;
+    let mut truncated = false;
+    let mut has_exponent = false;
 
-    let mut c = inp_iter.next()?;
-    if ['+','-'].contains(&c){
-        neg = c == '-';
-        c = inp_iter.next()?;
+    while let Some(c) = cursor.peek() {
+        match c {
+            b'.' if !decimal_seen => decimal_seen = true,
+            b'p' | b'P' if any_digits => {
+                // Mantissa is done: this is the start of the exponent
+                has_exponent = true;
+                cursor.next();
+                break;
+            }
+            _ => {
+                let d = match char::from(c).to_digit(16) {
+                    Some(d) => u128::from(d),
+                    None => break,  // Non-hex digit (or non-ASCII byte) ends the number
+                };
+                any_digits = true;
+                if mantissa >> 124 == 0 {
+                    mantissa = (mantissa << 4) | d;
+                    if decimal_seen {
+                        exp2 = exp2.saturating_sub(4);
+                    }
+                } else {
+                    drop_digits(d != 0, 4, decimal_seen, &mut truncated, &mut exp2);
+                }
+            }
+        }
+        cursor.next();
+    }
+
+    if !any_digits {
+        return Err(match cursor.peek() {
+            None | Some(b'.') | Some(b'p') | Some(b'P') => ParseError::MissingDigits(cursor.offset()),
+            Some(c) => ParseError::unexpected(c, cursor.offset(), decimal_seen),
+        });
     }
 
-    exp10 = c.to_digit(10)?.into();
-    while let Some(c) = inp_iter.next() {
-        if c == '_' {
-            continue
+    Ok((mantissa, exp2, truncated, has_exponent))
+}
+
+/// Drops digits from a mantissa that is out of room, but remembers if any
+/// were non-zero. Dropped integer digits still scale the mantissa by `scale`
+/// in the exponent; dropped fraction digits do not.
+fn drop_digits(non_zero: bool, scale: i32, decimal_seen: bool, truncated: &mut bool, exp: &mut i32) {
+    *truncated |= non_zero;
+    if !decimal_seen {
+        *exp = exp.saturating_add(scale);
+    }
+}
+
+/// The value of the first eight bytes, if they are all ASCII digits,
+/// computed a word at a time (SWAR) rather than a digit at a time.
+pub fn read_eight_digits(bytes: &[u8]) -> Option<u64> {
+    let chunk: [u8; 8] = bytes.get(..8)?.try_into().ok()?;
+    let v = u64::from_le_bytes(chunk);
+    // A byte is a digit iff its high nibble is 3, and still is after adding 6
+    let high_nibbles = (v & 0xf0f0_f0f0_f0f0_f0f0) | ((v.wrapping_add(0x0606_0606_0606_0606) & 0xf0f0_f0f0_f0f0_f0f0) >> 4);
+    if high_nibbles != 0x3333_3333_3333_3333 {
+        return None;
+    }
+    Some(eight_digits_value(v))
+}
+
+/// The value of eight ASCII digits, loaded little-endian into a word.
+pub fn eight_digits_value(v: u64) -> u64 {
+    // Little-endian, so the first digit is in the low byte. Combine adjacent
+    // digits into two-digit values in every other byte, then those into the
+    // two four-digit halves, each with a multiply that also scales them.
+    let v = v - 0x3030_3030_3030_3030;
+    let v = v * 10 + (v >> 8);
+    let low_pairs = (v & 0x0000_00ff_0000_00ff).wrapping_mul(100 + (1_000_000 << 32));
+    let high_pairs = ((v >> 16) & 0x0000_00ff_0000_00ff).wrapping_mul(1 + (10_000 << 32));
+    low_pairs.wrapping_add(high_pairs) >> 32
+}
+
+/// Parses an exponent starting AFTER `e` or `E` (or a hex float's `p`),
+/// saturating at +/- i32::MAX.
+/// Fails unless the exponent has a digit (after an optional sign and any
+/// underscores), and otherwise stops at the first character that cannot
+/// extend it.
+pub fn parse_exp10(cursor: &mut Cursor, fmt: &Format) -> Result<i32, ParseError> {
+    let mut neg = false;
+    if let Some(c @ (b'+' | b'-')) = cursor.peek() {
+        neg = c == b'-';
+        cursor.next();
+    }
+
+    // Exponents this large already overflow or underflow any float, so we
+    // saturate rather than fail.
+    let mut exp10 = 0i32;
+    let mut any_digits = false;
+    let mut prev_digit = false;
+    while let Some(c) = cursor.peek() {
+        if c.is_ascii_digit() {
+            exp10 = exp10.saturating_mul(10).saturating_add(i32::from(c - b'0'));
+            any_digits = true;
+            prev_digit = true;
+        } else if c == b'_' && underscore_allowed(cursor, fmt.underscores, true, prev_digit) {
+            prev_digit = false;
+        } else {
+            break;
         }
-        exp10 = exp10.checked_mul(10)?;
-        let d: i64 = c.to_digit(10)?.into();
-        exp10 = exp10.checked_add(d)?;
+        cursor.next();
+    }
+    if !any_digits {
+        return Err(ParseError::MissingExponentDigits(cursor.offset()));
+    }
+    if neg { exp10 = -exp10 }
+    Ok(exp10)
+}
+
+/// Recognizes `inf`, `infinity` or `nan` (with any payload) at the cursor,
+/// as the rules allow, and advances past it.
+pub fn parse_special(cursor: &mut Cursor, rules: &SpecialValues) -> Option<Special> {
+    let starts_with = |word: &[u8]| match cursor.rest().get(..word.len()) {
+        Some(start) if rules.any_case => start.eq_ignore_ascii_case(word),
+        Some(start) => start == word,
+        None => false,
+    };
+    if rules.infinity && starts_with(b"infinity") {
+        cursor.advance(8);
+        Some(Special::Infinity)
+    } else if starts_with(b"inf") {
+        cursor.advance(3);
+        Some(Special::Infinity)
+    } else if starts_with(b"nan") {
+        cursor.advance(3);
+        let payload = if rules.nan_payload { parse_nan_payload(cursor) } else { None };
+        Some(Special::Nan(payload.unwrap_or(0)))
+    } else {
+        None
+    }
+}
+
+/// Parses a parenthesized n-char-sequence after a NaN, advancing past it.
+/// As with `strtod`, any run of ASCII letters, digits and underscores is
+/// accepted, but it is only the payload if it is a decimal or `0x` hex
+/// number; anything else, like `nan(abc)`, gives the default quiet NaN. If
+/// there is no closing parenthesis, as in `nan(12`, the cursor does not move
+/// and the NaN ends before the parenthesis. Payloads wrap modulo 2 ** 128;
+/// only the low bits are kept anyway.
+fn parse_nan_payload(cursor: &mut Cursor) -> Option<u128> {
+    let mut probe = cursor.clone();
+    if probe.next()? != b'(' {
+        return None;
+    }
+    let len = probe.rest().iter().take_while(|&&c| c.is_ascii_alphanumeric() || c == b'_').count();
+    let sequence = &probe.rest()[..len];
+    probe.advance(len);
+    if probe.next()? != b')' {
+        return None;
+    }
+    *cursor = probe;
+
+    let (digits, radix) = match sequence {
+        [b'0', b'x' | b'X', digits @ ..] => (digits, 16),
+        _ => (sequence, 10),
+    };
+    if digits.is_empty() {
+        return Some(0);
+    }
+    let payload = digits.iter().try_fold(0u128, |payload, &c| {
+        let d = char::from(c).to_digit(radix)?;
+        Some(payload.wrapping_mul(u128::from(radix)).wrapping_add(u128::from(d)))
+    });
+    Some(payload.unwrap_or(0))
+}
+
+/// Whether the underscore at the cursor may be skipped, given whether the
+/// current run of digits has started and whether a digit precedes it.
+fn underscore_allowed(cursor: &Cursor, rule: Underscores, after_digit: bool, prev_digit: bool) -> bool {
+    match rule {
+        Underscores::Never => false,
+        Underscores::Anywhere => true,
+        Underscores::AfterDigit => after_digit,
+        Underscores::BetweenDigits => prev_digit && cursor.peek_nth(1).is_some_and(|c| c.is_ascii_digit()),
+    }
+}
+
+/// The whitespace characters of C's `isspace`
+pub fn is_space(c: u8) -> bool {
+    matches!(c, b' ' | b'\t' | b'\n' | b'\x0b' | b'\x0c' | b'\r')
+}
+
+/// Recognizes an `f32` or `f64` suffix at the start of `rest`.
+pub fn parse_suffix(rest: &[u8]) -> Option<Suffix> {
+    match rest.get(..3)? {
+        b"f32" => Some(Suffix::F32),
+        b"f64" => Some(Suffix::F64),
+        _ => None,
     }
-    let mut exp10: i16 = exp10.try_into().ok()?;
-    if neg { exp10 = exp10.checked_mul(-1)? }
-    Some(exp10)
 }
 
 }
 
 #[cfg(test)]
 pub mod tests {
-    use crate::elparse::{parse_parts::{parse_exp10, parse_leading_sign}, parse_man_exp10, ManExp10};
+    use crate::elparse::{parse_parts::{parse_exp10, parse_leading_sign}, parse_man_exp10, parse_float, parse_float_bytes, parse_float_partial, parse_float_with_grammar, parse_f64_const, parse_float_internal, eisel_lemire, ManExp10};
+    use crate::float::{RawFloat, F16};
+    use crate::lookups::{lut_entry_width, tests::lut_covers};
 
-    use super::{parse_parts::parse_mantissa_base10};
+    use super::{parse_parts::{parse_mantissa_base10, read_eight_digits, scan_mantissa_base10, Cursor}};
+    use crate::error::ParseError::{self, *};
+    use crate::grammar::Grammar;
     use std::collections::HashMap;
     use rand::random;
 
     #[test]
     fn check_parse_exp10(){
-        let inputs = vec!["-2639", "+173", "0_00___0", "0+0_0", "999999", ""];
-        let outputs = vec![Some(-2639i16), Some(173), Some(0), None, None, None];
-        for (i, o) in inputs.into_iter().zip(outputs.into_iter()){
-            let testout = parse_exp10(&mut i.chars());
-            assert_eq!(testout, o, "Input {} should parse to {:?} but got {:?}", i, o, testout)
+        let inputs = vec!["-2639", "+173", "0_00___0", "0+0_0", "999999", "", "-99999999999999", "+x", "12]", "-_1"];
+        let outputs = vec![Ok(-2639i32), Ok(173), Ok(0), Ok(0), Ok(999999), Err(MissingExponentDigits(0)), Ok(-i32::MAX),
+                           Err(MissingExponentDigits(1)), Ok(12), Ok(-1)];
+        let rests = ["", "", "", "+0_0", "", "", "", "", "]", ""];
+        for ((i, o), r) in inputs.into_iter().zip(outputs).zip(rests.iter()){
+            let mut cursor = Cursor::new(i.as_bytes());
+            let testout = parse_exp10(&mut cursor, &Grammar::Loose.format());
+            assert_eq!(testout, o, "Input {} should parse to {:?} but got {:?}", i, o, testout);
+            if testout.is_ok() {
+                assert_eq!(cursor.rest(), r.as_bytes(), "Parsing {} should have left {:?} in the stream", i, r);
+            }
         }
     }
 
     #[test]
     fn check_parse_sign_net(){
         let inputs = vec!["-3","+7","00","90","-",""];
-        let outputs = [Some(true), Some(false), Some(false), Some(false), Some(true), None];
-        let nexts = [Some('3'),Some('7'),Some('0'),Some('9'), None, None];
+        let outputs = [Ok(true), Ok(false), Ok(false), Ok(false), Ok(true), Err(Empty)];
+        let nexts = [Some(b'3'),Some(b'7'),Some(b'0'),Some(b'9'), None, None];
         for ((i, o),n) in inputs.into_iter().zip(outputs.iter()).zip(nexts.iter()){
-            let mut itr = i.chars();
-            let testout = parse_leading_sign(&mut itr);
-            assert_eq!(testout, o.clone(), "Parsing sign of {} should have given {:?} but gave {:?}",i,o,testout);
-            let testnext = itr.next();
-            assert_eq!(testnext, n.clone(), "Parsing {} should have left {:?} as next in stream, but got {:?}", i, n, testnext )
+            let mut cursor = Cursor::new(i.as_bytes());
+            let testout = parse_leading_sign(&mut cursor, &Grammar::Loose.format());
+            assert_eq!(testout, *o, "Parsing sign of {} should have given {:?} but gave {:?}",i,o,testout);
+            let testnext = cursor.next();
+            assert_eq!(testnext, *n, "Parsing {} should have left {:?} as next in stream, but got {:?}", i, n, testnext )
         }
     }
 
@@ -229,12 +1042,57 @@ pub mod tests {
     fn check_parse_mantissa(){
         // Note: in Rust, "123" is an int, not a float, but we will allow it to be
         // a float for the sake of the EL-parsing
-        let inputs = ["123.45e10", "123.", "123e1","+"];
-        let outputs = [Some((12345u64, -2i16, true)), Some((123, 0, false)), Some((123,0,true)), None];
-        for (i, o) in inputs.iter().zip(outputs.iter()){
-            let mut itr = i.chars();
-            let testout = parse_mantissa_base10(&mut itr);
-            assert_eq!(testout, o.clone(), "Parsing mantissa of {} should have given {:?} but it gave {:?}", i, o, testout);
+        let inputs = ["123.45e10", "123.", "123e1","+", ".", "e5", "1.2.3", "3.5,4.2", "", "..1", "_x"];
+        let outputs = [Ok((12345u64, -2i32, false, true)), Ok((123, 0, false, false)), Ok((123, 0, false, true)),
+                       Err(InvalidSign(0)), Err(MissingDigits(1)), Err(MissingDigits(0)),
+                       Ok((12, -1, false, false)), Ok((35, -1, false, false)), Err(MissingDigits(0)),
+                       Err(MultipleDecimalPoints(1)), Err(InvalidDigit(1))];
+        let rests = ["10", "", "1", "", "", "", ".3", ",4.2", "", "", ""];
+        for ((i, o), r) in inputs.iter().zip(outputs.iter()).zip(rests.iter()){
+            let mut cursor = Cursor::new(i.as_bytes());
+            let testout = parse_mantissa_base10(&mut cursor, &Grammar::Loose.format());
+            assert_eq!(testout, *o, "Parsing mantissa of {} should have given {:?} but it gave {:?}", i, o, testout);
+            if testout.is_ok() {
+                assert_eq!(cursor.rest(), r.as_bytes(), "Parsing {} should have left {:?} in the stream", i, r);
+            }
+        }
+    }
+
+    #[test]
+    fn check_read_eight_digits(){
+        assert_eq!(read_eight_digits(b"12345678"), Some(12_345_678));
+        assert_eq!(read_eight_digits(b"00000000x"), Some(0));
+        assert_eq!(read_eight_digits(b"99999999"), Some(99_999_999));
+        assert_eq!(read_eight_digits(b"1234567"), None);
+        for i in 0..8 {
+            for bad in [b'/', b':', b'.', b'_', b'e', b'0' + 0x80, b'0' - 0x10, b'9' + 6, 0xff].iter() {
+                let mut chunk = *b"12345678";
+                chunk[i] = *bad;
+                assert_eq!(read_eight_digits(&chunk), None, "{:?} is not all digits", chunk);
+            }
+        }
+        for _ in 0..10_000 {
+            let v = random::<u64>() % 100_000_000;
+            assert_eq!(read_eight_digits(format!("{:08}", v).as_bytes()), Some(v));
+        }
+    }
+
+    #[test]
+    fn check_swar_matches_scalar(){
+        // Mostly digits, so that runs of eight are common
+        let alphabet = b"000123456789999._e";
+        let grammars = [Grammar::Loose, Grammar::Json, Grammar::Python, Grammar::Toml, Grammar::RustLiteral];
+        for _ in 0..100_000 {
+            let len = random::<usize>() % 40;
+            let input: Vec<u8> = (0..len).map(|_| alphabet[random::<usize>() % alphabet.len()]).collect();
+            for grammar in grammars.iter() {
+                let fmt = grammar.format();
+                let (mut swar, mut scalar) = (Cursor::new(&input), Cursor::new(&input));
+                let swar_out = scan_mantissa_base10(&mut swar, &fmt, true);
+                let scalar_out = scan_mantissa_base10(&mut scalar, &fmt, false);
+                assert_eq!(swar_out, scalar_out, "{:?} in {:?}", String::from_utf8_lossy(&input), grammar);
+                assert_eq!(swar.offset(), scalar.offset(), "{:?} in {:?}", String::from_utf8_lossy(&input), grammar);
+            }
         }
     }
 
@@ -243,31 +1101,483 @@ pub mod tests {
     fn check_man_exp10_form(){
         let test_data = HashMap::from([
             // Valid Numbers with exponent
-            ("137.25e+17",Some(ManExp10{neg: false, man:13725, e10: 15})),
-            ("-137.25e17", Some(ManExp10{neg: true, man:13725, e10: 15})),
-            ("-137.25e-17", Some(ManExp10{neg: true, man:13725, e10: -19})),
-            ("24e3", Some(ManExp10{neg: false, man: 24, e10: 3})),
-            ("-24e3", Some(ManExp10{neg: true, man: 24, e10: 3})),
-            ("-24000e-3", Some(ManExp10{neg: true, man: 24000, e10: -3})),
+            ("137.25e+17",Ok(ManExp10{neg: false, man:13725, e10: 15, truncated: false})),
+            ("-137.25e17", Ok(ManExp10{neg: true, man:13725, e10: 15, truncated: false})),
+            ("-137.25e-17", Ok(ManExp10{neg: true, man:13725, e10: -19, truncated: false})),
+            ("24e3", Ok(ManExp10{neg: false, man: 24, e10: 3, truncated: false})),
+            ("-24e3", Ok(ManExp10{neg: true, man: 24, e10: 3, truncated: false})),
+            ("-24000e-3", Ok(ManExp10{neg: true, man: 24000, e10: -3, truncated: false})),
             // Naughty exponents
-            ("125.25e-16-12", None),
-            ("125e+-112", None),
-            ("-125e-112.7", None),
-            ("+125e999999", None),  // Exponent overflows i16
+            ("125.25e-16-12", Err(InvalidSign(10))),
+            ("125e+-112", Err(MissingExponentDigits(5))),
+            ("-125e-112.7", Err(InvalidDigit(9))),
+            ("1e", Err(MissingExponentDigits(2))),
+            ("+125e999999", Ok(ManExp10{neg: false, man: 125, e10: i16::MAX, truncated: false})),  // Exponent saturates
             // Valid Numbers without exponent
-            ("2.56", Some(ManExp10{neg: false, man: 256, e10: -2})),
-            ("-2.56", Some(ManExp10{neg: true, man: 256, e10: -2})),
-            ("3.", Some(ManExp10{neg: false, man: 3, e10: 0})),
-            ("+.2777", Some(ManExp10{neg: false, man: 2777, e10: -4})),
+            ("2.56", Ok(ManExp10{neg: false, man: 256, e10: -2, truncated: false})),
+            ("-2.56", Ok(ManExp10{neg: true, man: 256, e10: -2, truncated: false})),
+            ("3.", Ok(ManExp10{neg: false, man: 3, e10: 0, truncated: false})),
+            ("+.2777", Ok(ManExp10{neg: false, man: 2777, e10: -4, truncated: false})),
+            // Long mantissas keep the first 19 significant digits
+            ("0.000123456789012345678901", Ok(ManExp10{neg: false, man: 1234567890123456789, e10: -22, truncated: true})),
+            ("12345678901234567890000", Ok(ManExp10{neg: false, man: 1234567890123456789, e10: 4, truncated: false})),
+            ("-1234567890123456789.5e3", Ok(ManExp10{neg: true, man: 1234567890123456789, e10: 3, truncated: true})),
             // Pathologies
-            ("", None),
-            ("--2.5", None),
-            ("-+2.5", None),
-            ("2.5.3", None),
+            ("", Err(Empty)),
+            ("--2.5", Err(InvalidSign(1))),
+            ("-+2.5", Err(InvalidSign(1))),
+            ("2.5.3", Err(MultipleDecimalPoints(3))),
+            ("2.5x", Err(InvalidDigit(3))),
+            ("-", Err(MissingDigits(1))),
         ]);
         for (i, o) in test_data.iter(){
-            let testout = parse_man_exp10(i);
-            assert_eq!(testout, *o.clone(), "Parsing {} should have resulted in {:?} but got {:?}", i, o, testout);
+            let testout = parse_man_exp10(i.as_bytes());
+            assert_eq!(testout, *o, "Parsing {} should have resulted in {:?} but got {:?}", i, o, testout);
+        }
+    }
+
+    /// Whether the power-of-ten table covers the exponent of an input, so
+    /// that the fast path should handle it as often as with the full table.
+    pub fn lut_covers_input<F: RawFloat>(input: &[u8]) -> bool {
+        lut_covers::<F>(parse_man_exp10(input).unwrap().e10)
+    }
+
+    // The full parse must be bit-exact with std, and so must the fast path
+    // whenever it produces an answer. Returns whether it did.
+    fn check_against_std(input: &str) -> bool {
+        let expected: f64 = input.parse().unwrap();
+        let testout = parse_float::<f64>(input).unwrap();
+        assert_eq!(testout.to_bits(), expected.to_bits(), "Parsing {} should have given {:e} but got {:e}", input, expected, testout);
+        match parse_float_internal::<f64>(input.as_bytes()) {
+            Some(f) => {
+                assert_eq!(f.to_bits(), expected.to_bits(), "Parsing {} should have given {:e} but got {:e}", input, expected, f);
+                true
+            }
+            None => false,
+        }
+    }
+
+    #[test]
+    fn check_known_floats(){
+        let inputs = ["0.1", "-0.3", "3.141592653589793", "8.98846567431158e307",
+                      "2.2250738585072014e-308", "123456789012345678e-10", "1.7976931348623157e308"];
+        for i in inputs.iter() {
+            assert!(check_against_std(i) || !lut_covers_input::<f64>(i.as_bytes()), "Fast path should have handled {}", i);
+        }
+    }
+
+    #[test]
+    fn check_random_floats(){
+        // Only inputs that the table covers count, so that a table narrowed
+        // at build time still has to do its share
+        let (mut covered, mut handled) = (0, 0);
+        for _ in 0..100_000 {
+            let f = f64::from_bits(random::<u64>());
+            if !f.is_finite() {
+                continue;
+            }
+            // Shortest round-trip representation: at most 17 digits
+            let input = format!("{:e}", f);
+            let handled_here = check_against_std(&input);
+            if lut_covers_input::<f64>(input.as_bytes()) {
+                covered += 1;
+                handled += handled_here as usize;
+            }
+        }
+        assert!(handled * 10 >= covered * 9, "Fast path only handled {} of {} inputs", handled, covered);
+    }
+
+    #[test]
+    fn check_random_mantissas(){
+        let (mut covered, mut handled) = (0, 0);
+        for _ in 0..100_000 {
+            let man = random::<u64>() % 10_000_000_000_000_000_000;
+            // Stay clear of the subnormal and overflow ranges
+            let e10 = random::<i16>() % 280;
+            let input = format!("{}e{}", man, e10);
+            let handled_here = check_against_std(&input);
+            if lut_covers_input::<f64>(input.as_bytes()) {
+                covered += 1;
+                handled += handled_here as usize;
+            }
+        }
+        assert!(handled * 100 >= covered * 99, "Fast path only handled {} of {} inputs", handled, covered);
+    }
+
+    #[test]
+    fn check_invalid_inputs(){
+        let inputs = ["", "-", "+", ".", "e5", "-e5", "1e", "1.2.3", "1x", "--1", "infx", "nan(1)", "in"];
+        for i in inputs.iter() {
+            assert!(parse_float::<f64>(i).is_err(), "{} should not parse as a float", i);
+        }
+    }
+
+    #[test]
+    fn check_parse_errors(){
+        let inputs = [("", Empty), ("1,5", InvalidDigit(1)), ("1.5.", MultipleDecimalPoints(3)), ("-.e4", MissingDigits(2)),
+                      // A point in the exponent is not a second one
+                      ("1e5.0", InvalidDigit(3)), ("1.5e5.0", MultipleDecimalPoints(5)), ("-2e-3.", InvalidDigit(5)),
+                      ("2.5e+", MissingExponentDigits(5)), ("+-1", InvalidSign(1)), ("\u{2212}1", InvalidDigit(0)),
+                      ("123456789012345678901234567890x", InvalidDigit(30))];
+        for (i, e) in inputs.iter() {
+            let testout = parse_float::<f64>(i);
+            assert_eq!(testout, Err(*e), "Parsing {} should have failed with {:?} but got {:?}", i, e, testout);
+        }
+
+        // Partial parsing fails only if no prefix is a float
+        assert_eq!(parse_float_partial::<f64>(b"-x"), Err(InvalidDigit(1)));
+        assert_eq!(parse_float_partial::<f64>(b"2.5e+"), Ok((2.5, 3)));
+
+        let e: ParseError = parse_float::<f32>("12.5e9x").unwrap_err();
+        assert_eq!(e.offset(), 6);
+        assert_eq!(e.to_string(), "invalid digit at byte 6");
+    }
+
+    #[test]
+    fn check_special_values(){
+        // The same spellings as str::parse
+        let inputs = ["inf", "-inf", "+INF", "Infinity", "-iNfInItY", "nan", "NaN", "+nan", "-NAN"];
+        for i in inputs.iter() {
+            let expected: f64 = i.parse().unwrap();
+            let testout = parse_float::<f64>(i).unwrap();
+            assert_eq!(testout.to_bits() & !(1 << 63), expected.to_bits() & !(1 << 63), "Parsing {} should have given {:e} but got {:e}", i, expected, testout);
+            assert_eq!(testout.is_sign_negative(), i.starts_with('-'), "Parsing {} gave the wrong sign", i);
+        }
+        assert_eq!(parse_float::<f32>("-Infinity"), Ok(f32::NEG_INFINITY));
+        assert_eq!(parse_float::<f32>("nan").map(f32::to_bits), Ok(0x7fc0_0000));
+        assert_eq!(parse_float::<F16>("-inf").map(F16::to_bits), Ok(0xfc00));
+        assert_eq!(parse_float::<F16>("nan").map(F16::to_bits), Ok(0x7e00));
+
+        // Infinity and NaN take no suffix, and the loose grammar takes no payload
+        let invalid = [("inff32", InvalidDigit(3)), ("infinit", InvalidDigit(3)), ("nan(0x1)", InvalidDigit(3)),
+                       ("inf1", InvalidDigit(3)), ("i", InvalidDigit(0)), ("- inf", InvalidDigit(1))];
+        for (i, e) in invalid.iter() {
+            let testout = parse_float::<f64>(i);
+            assert_eq!(testout, Err(*e), "Parsing {} should have failed with {:?} but got {:?}", i, e, testout);
+        }
+        assert_eq!(parse_float_partial::<f64>(b"infinit"), Ok((f64::INFINITY, 3)));
+        assert_eq!(parse_float_partial::<f64>(b"-infinity,"), Ok((f64::NEG_INFINITY, 9)));
+        assert!(parse_float_partial::<f64>(b"nan(0x1)").is_ok_and(|(x, len)| x.is_nan() && len == 3));
+    }
+
+    #[test]
+    fn check_hex_floats(){
+        let min_subnormal = f64::from_bits(1);
+        let inputs = [("0x1.8p3", 12.0), ("-0X1P+4", -16.0), ("0x.8p1", 1.0), ("0x1.", 1.0), ("0xa", 10.0),
+                      ("0x0p0", 0.0), ("-0x0.000p-5", -0.0), ("0x1.921fb54442d18p1", std::f64::consts::PI),
+                      ("0x1p-1022", f64::MIN_POSITIVE), ("0x0.fffffffffffffp-1022", f64::MIN_POSITIVE - min_subnormal),
+                      ("0x1p-1074", min_subnormal), ("0x1p-1075", 0.0), ("0x1.0000000000000000000000000000000000001p-1075", min_subnormal),
+                      ("0x3p-1076", min_subnormal), ("0x1.fffffffffffffp1023", f64::MAX), ("0x1.fffffffffffff7ffffp1023", f64::MAX),
+                      ("0x1.fffffffffffff8p1023", f64::INFINITY), ("0x1p99999999999", f64::INFINITY), ("0x1p-99999999999", 0.0),
+                      // Ties go to even, unless truncated digits put us above half-way
+                      ("0x1.00000000000008p0", 1.0), ("0x1.00000000000018p0", 1.0 + 2.0 * f64::EPSILON),
+                      ("0x1.00000000000008000000000000000000000000001p0", 1.0 + f64::EPSILON),
+                      ("0x100000000000000000000000000000000000000000000p-176", 1.0)];
+        for (i, o) in inputs.iter() {
+            let testout = parse_float_with_grammar::<f64>(i.as_bytes(), Grammar::C).unwrap();
+            assert_eq!(testout.to_bits(), o.to_bits(), "Parsing {} should have given {:e} but got {:e}", i, o, testout);
+        }
+
+        // Exactly rounding the hex form of a random f64 to f32 must agree with
+        // the cast, including subnormals and overflow
+        for _ in 0..100_000 {
+            // Exponent fields around the f32 range, and sometimes f64 subnormals
+            let e = (1023 - 160 + random::<u64>() % 300) * u64::from(random::<u8>() != 0);
+            let bits = (random::<u64>() & 0x800f_ffff_ffff_ffff) | (e << 52);
+            let x = f64::from_bits(bits);
+            let (sign, f) = (if x.is_sign_negative() { "-" } else { "" }, bits & ((1 << 52) - 1));
+            let hex = if e == 0 { format!("{}0x0.{:013x}p-1022", sign, f) } else { format!("{}0x1.{:013x}p{}", sign, f, e as i32 - 1023) };
+            let testout = parse_float_with_grammar::<f64>(hex.as_bytes(), Grammar::C).unwrap();
+            assert_eq!(testout.to_bits(), bits, "Parsing {} should have given {:e}", hex, x);
+            let testout = parse_float_with_grammar::<f32>(hex.as_bytes(), Grammar::C).unwrap();
+            assert_eq!(testout.to_bits(), (x as f32).to_bits(), "Parsing {} as f32 should have given {:e}", hex, x as f32);
+        }
+    }
+
+    #[test]
+    fn check_parse_f64_const(){
+        // Each of these is evaluated at compile time
+        const CONSTS: [(f64, f64); 10] = [
+            (parse_f64_const("6.67430e-11"), 6.67430e-11),
+            (parse_f64_const("-1_000.5e-3"), -1.0005),
+            (parse_f64_const("-0"), -0.0),
+            (parse_f64_const(".5"), 0.5),
+            (parse_f64_const("1e-400"), 0.0),
+            (parse_f64_const("1e400"), f64::INFINITY),
+            (parse_f64_const("4.9406564584124654e-324"), f64::from_bits(1)),
+            // Halfway cases that need the decimal slow path
+            (parse_f64_const("9007199254740993"), 9007199254740992.0),
+            (parse_f64_const("9007199254740993.00000000000000000001"), 9007199254740994.0),
+            (parse_f64_const("2.4703282292062327208828439643411068618252990130716238221279284125033775364e-324"), f64::from_bits(1)),
+        ];
+        for (testout, expected) in CONSTS.iter() {
+            assert_eq!(testout.to_bits(), expected.to_bits(), "Got {:e} instead of {:e}", testout, expected);
+        }
+
+        for _ in 0..10_000 {
+            let f = f64::from_bits(random::<u64>());
+            if f.is_finite() {
+                let input = format!("{:e}", f);
+                assert_eq!(parse_f64_const(&input).to_bits(), parse_float::<f64>(&input).unwrap().to_bits(), "Parsing {}", input);
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "not a decimal float")]
+    fn check_parse_f64_const_invalid(){
+        parse_f64_const("1.5f32");
+    }
+
+    #[test]
+    fn check_suffixes(){
+        // An f32 suffix rounds to f32, not to f64: 1.00000005960464477550 is
+        // just above the midpoint between 1.0 and the next f32
+        let inputs = [("1.00000005960464477550f32", f64::from(1.0000001f32)), ("1.00000005960464477550f64", 1.0000000596046448),
+                      ("1.5f32", 1.5), ("0.1f32", f64::from(0.1f32)), ("-1e-45f32", f64::from(-1e-45f32)),
+                      ("7e-46f32", 0.0), ("3.4028236e38f32", f64::INFINITY), ("1e39f32", f64::INFINITY),
+                      ("3.14159265358979323846264338327950288f32", f64::from(std::f32::consts::PI)), ("1f64", 1.0)];
+        for (i, o) in inputs.iter() {
+            let testout = parse_float::<f64>(i).unwrap();
+            assert_eq!(testout.to_bits(), o.to_bits(), "Parsing {} should have given {:e} but got {:e}", i, o, testout);
+        }
+
+        // An f64 suffix does not make an f32 round twice, which would give 1.0
+        assert_eq!(parse_float::<f32>("1.00000005960464477550f64").unwrap(), 1.0000001);
+        assert_eq!(parse_float_partial::<f64>(b"1.5f32,"), Ok((1.5, 6)));
+        assert_eq!(parse_float_partial::<f64>(b"1.5f3"), Ok((1.5, 3)));
+
+        let invalid = [("1.5f", InvalidDigit(3)), ("1.5f16", InvalidDigit(3)), ("1.5F32", InvalidDigit(3)),
+                       ("1.5f32x", InvalidDigit(6)), ("f32", InvalidDigit(0)), ("1ef32", MissingExponentDigits(2))];
+        for (i, e) in invalid.iter() {
+            let testout = parse_float::<f64>(i);
+            assert_eq!(testout, Err(*e), "Parsing {} should have failed with {:?} but got {:?}", i, e, testout);
+        }
+    }
+
+    #[test]
+    fn check_parse_bytes(){
+        let inputs = ["12.375", "-0.1", "1e-400", "9007199254740993", "3.14159265358979323846264338327950288e-3"];
+        for i in inputs.iter() {
+            let expected: f64 = i.parse().unwrap();
+            let testout = parse_float_bytes::<f64>(i.as_bytes()).unwrap();
+            assert_eq!(testout.to_bits(), expected.to_bits(), "Parsing {} should have given {:e} but got {:e}", i, expected, testout);
+        }
+
+        // Non-ASCII is never part of a float, whether or not it is valid UTF-8
+        let invalid: [&[u8]; 6] = [b"1\xff", b"\xe2\x88\x921", "\u{ff11}".as_bytes(), b"1.5\x00", b"1e\xb5", b"\x80"];
+        for i in invalid.iter() {
+            assert!(parse_float_bytes::<f64>(i).is_err(), "{:?} should not parse as a float", i);
+        }
+    }
+
+    #[test]
+    fn check_parse_partial(){
+        let inputs = [("3.5,4.2", "3.5"), ("1e5]", "1e5"), ("1e", "1"), ("1E+", "1"), ("2.5e-x", "2.5"), ("-7", "-7"),
+                      ("1.2.3", "1.2"), ("12.375 ", "12.375"), ("0x10", "0"), ("1_000e1_0;", "1_000e1_0"),
+                      ("9007199254740993}", "9007199254740993"), ("1e-400,", "1e-400"), ("-.5e+3e4", "-.5e+3")];
+        for (i, prefix) in inputs.iter() {
+            let expected: f64 = prefix.replace('_', "").parse().unwrap();
+            let (testout, consumed) = parse_float_partial::<f64>(i.as_bytes()).unwrap();
+            assert_eq!(consumed, prefix.len(), "Parsing {} should have consumed {:?}", i, prefix);
+            assert_eq!(testout.to_bits(), expected.to_bits(), "Parsing {} should have given {:e} but got {:e}", i, expected, testout);
+        }
+
+        let invalid = ["", "-", "+e5", ".", ".e1", ",1", "\u{ff11}"];
+        for i in invalid.iter() {
+            assert!(parse_float_partial::<f64>(i.as_bytes()).is_err(), "{} should not parse as a float", i);
+        }
+    }
+
+    #[test]
+    fn check_slow_path_inputs(){
+        // Too long for the fast path, or exact halfway cases it cannot decide
+        let inputs = ["1.0", "9007199254740993", "3.14159265358979323846264338327950288",
+                      "4.9e-324", "1e400", "-0.000000000000000000000000000000001e-290"];
+        for i in inputs.iter() {
+            let expected: f64 = i.parse().unwrap();
+            let testout = parse_float::<f64>(i).unwrap();
+            assert_eq!(testout.to_bits(), expected.to_bits(), "Parsing {} should have given {:e} but got {:e}", i, expected, testout);
+        }
+    }
+
+    #[test]
+    fn check_long_mantissas(){
+        let (mut covered, mut handled) = (0, 0);
+        for _ in 0..10_000 {
+            let ndigits = 20 + random::<usize>() % 20;
+            let digits: String = (0..ndigits).map(|_| char::from(b'1' + random::<u8>() % 9)).collect();
+            let e10 = random::<i16>() % 250;
+            let input = format!("{}e{}", digits, e10);
+            let handled_here = check_against_std(&input);
+            if lut_covers_input::<f64>(input.as_bytes()) {
+                covered += 1;
+                handled += handled_here as usize;
+            }
+        }
+        assert!(handled * 100 >= covered * 99, "Fast path only handled {} of {} long inputs", handled, covered);
+    }
+
+    #[test]
+    fn check_subnormals(){
+        let inputs = ["4.9e-324", "-4.9e-324", "2.2250738585072011e-308", "2.2250738585072009e-308",
+                      "2.4703282292062328e-324", "2.4703282292062327e-324", "1e-320", "-3e-340", "1e-400",
+                      "123456789012345678901234567890e-350"];
+        for i in inputs.iter() {
+            assert!(check_against_std(i) || !lut_covers_input::<f64>(i.as_bytes()), "Fast path should have handled {}", i);
+        }
+
+        let (mut covered, mut handled) = (0, 0);
+        for _ in 0..10_000 {
+            let f = f64::from_bits(random::<u64>() & ((1 << 52) - 1));
+            let input = format!("{:e}", f);
+            let handled_here = check_against_std(&input);
+            if lut_covers_input::<f64>(input.as_bytes()) {
+                covered += 1;
+                handled += handled_here as usize;
+            }
+        }
+        assert!(handled * 100 >= covered * 99, "Fast path only handled {} of {} subnormals", handled, covered);
+    }
+
+    #[test]
+    fn check_overflow(){
+        let inputs = ["1e309", "-1e400", "9e99999999", "1.7976931348623159e308", "-1.7976931348623159e308",
+                      "1.7976931348623157e308", "1.7976931348623158e308", "179769313486231580793728971405303e275",
+                      "0.00000000000000000000000000000000000000001e330", "1e32767", "1e32768"];
+        for i in inputs.iter() {
+            assert!(check_against_std(i) || !lut_covers_input::<f64>(i.as_bytes()), "Fast path should have handled {}", i);
+        }
+
+        // Just either side of the halfway point between f64::MAX and 2 ** 1024
+        let inputs = ["1.7976931348623158079e308", "1.79769313486231580793e308", "1.797693134862315807937289714053e308",
+                      "1.797693134862315807937289714054e308"];
+        for i in inputs.iter() {
+            let expected: f64 = i.parse().unwrap();
+            assert_eq!(parse_float::<f64>(i).unwrap().to_bits(), expected.to_bits(), "Parsing {} should have given {:e}", i, expected);
+        }
+    }
+
+    #[test]
+    fn check_signed_zeros(){
+        let inputs = ["0", "-0", "+0", "-0.0", "-0e10", "-.0e-10", "-0000.0000e99999", "-1e-400", "-2.4e-324"];
+        for i in inputs.iter() {
+            let expected: f64 = i.parse().unwrap();
+            let testout = parse_float::<f64>(i).unwrap();
+            assert_eq!(testout.to_bits(), expected.to_bits(), "Parsing {} should have given {:e} but got {:e}", i, expected, testout);
+        }
+    }
+
+    #[test]
+    fn check_random_zeros(){
+        for _ in 0..10_000 {
+            let sign = ["", "-", "+"][random::<usize>() % 3];
+            let int_zeros = "0".repeat(random::<usize>() % 5 + 1);
+            let frac_zeros = "0".repeat(random::<usize>() % 30);
+            // Either a literal zero, or a non-zero digit pushed below the subnormals
+            let last = if random::<bool>() { "0" } else { "1" };
+            let e10 = if last == "0" { random::<i32>() } else { -350 - (random::<i32>() % 1000).abs() };
+            let input = format!("{}{}.{}{}e{}", sign, int_zeros, frac_zeros, last, e10);
+
+            let expected: f64 = input.parse().unwrap();
+            let testout = parse_float::<f64>(&input).unwrap();
+            assert_eq!(testout.to_bits(), expected.to_bits(), "Parsing {} should have given {:e} but got {:e}", input, expected, testout);
+        }
+    }
+
+    #[test]
+    fn check_f32(){
+        // Parsing as f64 and narrowing would round twice and give 1.0 here
+        let inputs = ["1.00000005960464477550", "1.5", "-0.1", "3.4028235e38", "3.4028236e38", "1e39",
+                      "1.17549435e-38", "1e-45", "7e-46", "-7.1e-46", "16777217", "0.000000000000000000000000000000000000000000000000001"];
+        for i in inputs.iter() {
+            let expected: f32 = i.parse().unwrap();
+            let testout = parse_float::<f32>(i).unwrap();
+            assert_eq!(testout.to_bits(), expected.to_bits(), "Parsing {} should have given {:e} but got {:e}", i, expected, testout);
+        }
+    }
+
+    #[test]
+    fn check_random_f32(){
+        let (mut covered, mut handled) = (0, 0);
+        for _ in 0..100_000 {
+            let f = f32::from_bits(random::<u32>());
+            if !f.is_finite() {
+                continue;
+            }
+            // Also try the neighbourhood of the shortest representation
+            for input in [format!("{:e}", f), format!("{:.12e}", f)].iter() {
+                let expected: f32 = input.parse().unwrap();
+                let covered_here = lut_covers_input::<f32>(input.as_bytes());
+                covered += covered_here as usize;
+                if let Some(testout) = parse_float_internal::<f32>(input.as_bytes()) {
+                    assert_eq!(testout.to_bits(), expected.to_bits(), "Parsing {} should have given {:e} but got {:e}", input, expected, testout);
+                    handled += covered_here as usize;
+                }
+                let testout = parse_float::<f32>(input).unwrap();
+                assert_eq!(testout.to_bits(), expected.to_bits(), "Parsing {} should have given {:e} but got {:e}", input, expected, testout);
+            }
+        }
+        assert!(handled * 10 >= covered * 9, "Fast path only handled {} of {} f32 inputs", handled, covered);
+    }
+
+    // Mantissas whose 64-bit product has all ones below the kept bits. Some
+    // of these are also exact for Clinger's fast path, so call Eisel-Lemire
+    // directly. A 64-bit table cannot refine the product, so there they are
+    // left to the decimal fallback.
+    #[test]
+    fn check_wider_approximation(){
+        let inputs = ["197041135e-14", "583371807320961e-15", "453076182342266327e12",
+                      "94318725749e-13", "26064406025e-16", "214313333933948e11"];
+        for i in inputs.iter() {
+            let expected: f64 = i.parse().unwrap();
+            assert_eq!(parse_float::<f64>(i), Ok(expected), "Parsing {} gave the wrong result", i);
+            let ManExp10 { neg, man, e10, .. } = parse_man_exp10(i.as_bytes()).unwrap();
+            match eisel_lemire::<f64>(man, e10) {
+                Some(fp) => assert_eq!(f64::from_biased_fp(neg, fp), expected, "Parsing {} gave the wrong result", i),
+                // Without the low halves of the powers, these are left to the fallback
+                None => assert!(lut_entry_width() == 64 || !lut_covers::<f64>(e10), "Fast path should have handled {}", i),
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(not(all(target_arch = "x86", not(target_feature = "sse2"))))]
+    fn check_clinger_fast_path(){
+        // Exact mantissas and powers of ten, at the edges of the range
+        let inputs = [("12.375", 12375, -3), ("-9007199254740991e22", 9007199254740991, 22),
+                      ("9007199254740991e-22", 9007199254740991, -22), ("1e22", 1, 22), ("0.1", 1, -1)];
+        for (i, man, e10) in inputs.iter() {
+            let expected: f64 = i.parse().unwrap();
+            let f = f64::clinger_fast_path(i.starts_with('-'), *man, *e10).unwrap();
+            assert_eq!(f.to_bits(), expected.to_bits(), "Parsing {} should have given {:e} but got {:e}", i, expected, f);
+            assert!(check_against_std(i));
+        }
+
+        // Out of range for f64, or for f32
+        assert_eq!(f64::clinger_fast_path(false, 1 << 53, 0), None);
+        assert_eq!(f64::clinger_fast_path(false, 1, 23), None);
+        assert_eq!(f64::clinger_fast_path(false, 1, -23), None);
+        assert_eq!(f32::clinger_fast_path(false, 1 << 24, 0), None);
+        assert_eq!(f32::clinger_fast_path(false, 1, 11), None);
+        assert_eq!(f32::clinger_fast_path(false, 16777215, -10), Some(16777215e-10));
+
+        for _ in 0..10_000 {
+            let man = random::<u64>() % (1 << 53);
+            let e10 = random::<i16>() % 23;
+            let input = format!("{}e{}", man, e10);
+            assert!(check_against_std(&input));
+        }
+    }
+
+    #[cfg(all(feature = "stats", not(all(target_arch = "x86", not(target_feature = "sse2")))))]
+    #[test]
+    fn check_clinger_fast_path_count(){
+        // Other tests may run concurrently, so the count only grows
+        let before = super::clinger_fast_path_count();
+        for i in ["12.375", "-4.5", "1e22"].iter() {
+            assert!(check_against_std(i));
         }
+        assert!(super::clinger_fast_path_count() >= before + 3);
     }
 }
@@ -0,0 +1,71 @@
+/* The error type returned by the parsers in `crate::elparse`.
+
+Every error other than `Empty` carries the byte offset into the input at which
+the problem was detected, so callers can point users at the exact column.
+*/
+
+use core::fmt;
+
+/// Why an input is not a valid float, and where.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseError {
+    /// The input is empty
+    Empty,
+    /// The byte at this offset cannot appear here
+    InvalidDigit(usize),
+    /// A second decimal point at this offset
+    MultipleDecimalPoints(usize),
+    /// The mantissa has no digits; the offset is where one was expected
+    MissingDigits(usize),
+    /// The exponent has no digits; the offset is where one was expected
+    MissingExponentDigits(usize),
+    /// A sign at this offset, where none is allowed
+    InvalidSign(usize),
+    /// The number is an integer: a point or exponent was expected at this
+    /// offset
+    MissingPointOrExponent(usize),
+}
+
+impl ParseError {
+    /// The byte offset at which the error was detected. This is 0 for empty
+    /// input.
+    pub fn offset(&self) -> usize {
+        match *self {
+            ParseError::Empty => 0,
+            ParseError::InvalidDigit(offset)
+            | ParseError::MultipleDecimalPoints(offset)
+            | ParseError::MissingDigits(offset)
+            | ParseError::MissingExponentDigits(offset)
+            | ParseError::InvalidSign(offset)
+            | ParseError::MissingPointOrExponent(offset) => offset,
+        }
+    }
+
+    /// The error for an unexpected byte `c` at `offset`, where `point_seen`
+    /// says whether the mantissa already has a decimal point. A point after
+    /// the exponent marker is only an invalid digit.
+    pub(crate) fn unexpected(c: u8, offset: usize, point_seen: bool) -> ParseError {
+        match c {
+            b'.' if point_seen => ParseError::MultipleDecimalPoints(offset),
+            b'+' | b'-' => ParseError::InvalidSign(offset),
+            _ => ParseError::InvalidDigit(offset),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            ParseError::Empty => write!(f, "cannot parse float from empty string"),
+            ParseError::InvalidDigit(offset) => write!(f, "invalid digit at byte {}", offset),
+            ParseError::MultipleDecimalPoints(offset) => write!(f, "second decimal point at byte {}", offset),
+            ParseError::MissingDigits(offset) => write!(f, "expected a digit at byte {}", offset),
+            ParseError::MissingExponentDigits(offset) => write!(f, "expected an exponent digit at byte {}", offset),
+            ParseError::InvalidSign(offset) => write!(f, "unexpected sign at byte {}", offset),
+            ParseError::MissingPointOrExponent(offset) => write!(f, "expected a point or exponent at byte {}", offset),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ParseError {}
@@ -0,0 +1,501 @@
+/* Descriptions of the binary floating-point formats the parser can produce.
+
+Both the Eisel-Lemire fast path and the decimal slow path work in terms of a
+`BiasedFp`: the explicit mantissa bits and the biased exponent field of the
+result. Only the final step of packing those fields (plus a sign bit) into
+the target type depends on the concrete format.
+*/
+
+use core::convert::TryFrom;
+use core::fmt::Debug;
+
+use crate::decimal::DOUBLE_MAX_DIGITS;
+
+/// An IEEE-754 style binary floating-point format.
+pub trait RawFloat: Copy + Debug + PartialEq {
+    /// The number of explicitly stored mantissa bits (excluding the hidden bit)
+    const MANTISSA_EXPLICIT_BITS: u32;
+    /// The width of the exponent field
+    const EXPONENT_BITS: u32;
+    /// A power of ten below which every 64-bit mantissa gives zero:
+    /// (2 ** 64 - 1) * 10 ** (SMALLEST_POWER_OF_TEN - 1) rounds to zero.
+    const SMALLEST_POWER_OF_TEN: i16;
+    /// The largest power of ten for which a mantissa of 1 gives a finite result
+    const LARGEST_POWER_OF_TEN: i16;
+    /// The significant digits the decimal slow path keeps: enough to round
+    /// any decimal to this format
+    const MAX_DIGITS: usize = DOUBLE_MAX_DIGITS;
+
+    /// The bias of the exponent field
+    const EXPONENT_BIAS: i32 = (1 << (Self::EXPONENT_BITS - 1)) - 1;
+    /// The value of the exponent field for infinities and NaNs
+    const INFINITE_POWER: i32 = (1 << Self::EXPONENT_BITS) - 1;
+    /// The unbiased exponent of an exponent field of zero
+    const MINIMUM_EXPONENT: i32 = -Self::EXPONENT_BIAS;
+
+    /// Reinterprets the low 1 + EXPONENT_BITS + MANTISSA_EXPLICIT_BITS bits
+    /// as a float of this format.
+    fn from_u128_bits(bits: u128) -> Self;
+
+    /// The bit pattern of the float, in the low bits of a u128.
+    fn to_u128_bits(self) -> u128;
+
+    /// Packs a sign and the fields of a BiasedFp into a float.
+    fn from_biased_fp(neg: bool, fp: BiasedFp) -> Self {
+        let exponent = u128::try_from(fp.e).unwrap();
+        let mut bits = (exponent << Self::MANTISSA_EXPLICIT_BITS) | fp.f;
+        if neg {
+            bits |= 1 << (Self::MANTISSA_EXPLICIT_BITS + Self::EXPONENT_BITS);
+        }
+        Self::from_u128_bits(bits)
+    }
+
+    /// Clinger's fast path: if man and 10 ** |e10| are both exactly
+    /// representable, a single native multiply or divide rounds correctly.
+    /// Returns None when that does not apply, and always for formats
+    /// without native arithmetic, or on x87 without SSE2.
+    fn clinger_fast_path(_neg: bool, _man: u64, _e10: i16) -> Option<Self> {
+        None
+    }
+}
+
+/// True if every value of G, including subnormals, is a normal value of F.
+pub fn is_narrower<G: RawFloat, F: RawFloat>() -> bool {
+    G::MANTISSA_EXPLICIT_BITS < F::MANTISSA_EXPLICIT_BITS && G::EXPONENT_BITS < F::EXPONENT_BITS
+}
+
+/// Converts a float to a format F, exactly. G must be narrower than F.
+pub fn widen<G: RawFloat, F: RawFloat>(x: G) -> F {
+    debug_assert!(is_narrower::<G, F>());
+    let bits = x.to_u128_bits();
+    let neg = (bits >> (G::MANTISSA_EXPLICIT_BITS + G::EXPONENT_BITS)) & 1 != 0;
+    let mantissa_mask = (1 << G::MANTISSA_EXPLICIT_BITS) - 1;
+    let mut f = bits & mantissa_mask;
+    let mut e = i32::try_from((bits >> G::MANTISSA_EXPLICIT_BITS) & ((1 << G::EXPONENT_BITS) - 1)).unwrap();
+
+    if e == G::INFINITE_POWER {
+        e = F::INFINITE_POWER;
+    } else if e != 0 || f != 0 {
+        if e == 0 {
+            // Subnormal: normalize, since F has the exponent range for it
+            e = 1;
+            while f >> G::MANTISSA_EXPLICIT_BITS == 0 {
+                f <<= 1;
+                e -= 1;
+            }
+            f &= mantissa_mask;
+        }
+        e += F::EXPONENT_BIAS - G::EXPONENT_BIAS;
+    }
+    let fp = BiasedFp { f: f << (F::MANTISSA_EXPLICIT_BITS - G::MANTISSA_EXPLICIT_BITS), e };
+    F::from_biased_fp(neg, fp)
+}
+
+/// The unsigned fields of a float: the explicit mantissa bits (with the
+/// hidden bit removed) and the biased exponent field.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct BiasedFp {
+    pub f: u128,
+    pub e: i32,
+}
+
+impl BiasedFp {
+    pub const fn zero() -> BiasedFp {
+        BiasedFp { f: 0, e: 0 }
+    }
+
+    pub const fn infinity<F: RawFloat>() -> BiasedFp {
+        BiasedFp { f: 0, e: F::INFINITE_POWER }
+    }
+
+    /// A quiet NaN, carrying as many low bits of `payload` as fit below the
+    /// quiet bit.
+    pub fn nan<F: RawFloat>(payload: u128) -> BiasedFp {
+        let quiet_bit = 1 << (F::MANTISSA_EXPLICIT_BITS - 1);
+        BiasedFp { f: quiet_bit | (payload & (quiet_bit - 1)), e: F::INFINITE_POWER }
+    }
+
+    /// The bits of the f64 with these fields and the given sign: the const
+    /// counterpart of `RawFloat::from_biased_fp`, for `parse_f64_const`.
+    pub const fn to_f64_bits(self, neg: bool) -> u64 {
+        ((neg as u64) << 63) | ((self.e as u64) << f64::MANTISSA_EXPLICIT_BITS) | self.f as u64
+    }
+}
+
+// Powers of ten that are exact in each format: 5 ** 22 < 2 ** 53 and
+// 5 ** 10 < 2 ** 24.
+//
+// The fast path relies on native arithmetic rounding once, in the target
+// format. x87 computes in extended precision and rounds again on the store,
+// so without SSE2 on 32-bit x86 it is left out and Eisel-Lemire does the work.
+#[cfg(not(all(target_arch = "x86", not(target_feature = "sse2"))))]
+const F64_EXACT_POW10: [f64; 23] = [
+    1e0, 1e1, 1e2, 1e3, 1e4, 1e5, 1e6, 1e7, 1e8, 1e9, 1e10, 1e11,
+    1e12, 1e13, 1e14, 1e15, 1e16, 1e17, 1e18, 1e19, 1e20, 1e21, 1e22,
+];
+#[cfg(not(all(target_arch = "x86", not(target_feature = "sse2"))))]
+const F32_EXACT_POW10: [f32; 11] = [1e0, 1e1, 1e2, 1e3, 1e4, 1e5, 1e6, 1e7, 1e8, 1e9, 1e10];
+
+impl RawFloat for f64 {
+    const MANTISSA_EXPLICIT_BITS: u32 = 52;
+    const EXPONENT_BITS: u32 = 11;
+    const SMALLEST_POWER_OF_TEN: i16 = -342;
+    const LARGEST_POWER_OF_TEN: i16 = 308;
+
+    fn from_u128_bits(bits: u128) -> Self {
+        f64::from_bits(u64::try_from(bits).unwrap())
+    }
+
+    fn to_u128_bits(self) -> u128 {
+        u128::from(self.to_bits())
+    }
+
+    #[cfg(not(all(target_arch = "x86", not(target_feature = "sse2"))))]
+    fn clinger_fast_path(neg: bool, man: u64, e10: i16) -> Option<Self> {
+        if man >> (Self::MANTISSA_EXPLICIT_BITS + 1) != 0 {
+            return None;
+        }
+        let pow10 = *F64_EXACT_POW10.get(usize::from(e10.unsigned_abs()))?;
+        // Exact, since man < 2 ** 53
+        let man = man as f64;
+        let value = if e10 < 0 { man / pow10 } else { man * pow10 };
+        Some(if neg { -value } else { value })
+    }
+}
+
+impl RawFloat for f32 {
+    const MANTISSA_EXPLICIT_BITS: u32 = 23;
+    const EXPONENT_BITS: u32 = 8;
+    const SMALLEST_POWER_OF_TEN: i16 = -65;
+    const LARGEST_POWER_OF_TEN: i16 = 38;
+
+    fn from_u128_bits(bits: u128) -> Self {
+        f32::from_bits(u32::try_from(bits).unwrap())
+    }
+
+    fn to_u128_bits(self) -> u128 {
+        u128::from(self.to_bits())
+    }
+
+    #[cfg(not(all(target_arch = "x86", not(target_feature = "sse2"))))]
+    fn clinger_fast_path(neg: bool, man: u64, e10: i16) -> Option<Self> {
+        if man >> (Self::MANTISSA_EXPLICIT_BITS + 1) != 0 {
+            return None;
+        }
+        let pow10 = *F32_EXACT_POW10.get(usize::from(e10.unsigned_abs()))?;
+        // Exact, since man < 2 ** 24
+        let man = man as f32;
+        let value = if e10 < 0 { man / pow10 } else { man * pow10 };
+        Some(if neg { -value } else { value })
+    }
+}
+
+/// IEEE-754 binary16 (half precision), stored as its bit pattern.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct F16(pub u16);
+
+impl F16 {
+    pub fn to_bits(self) -> u16 {
+        self.0
+    }
+}
+
+impl RawFloat for F16 {
+    const MANTISSA_EXPLICIT_BITS: u32 = 10;
+    const EXPONENT_BITS: u32 = 5;
+    const SMALLEST_POWER_OF_TEN: i16 = -26;
+    const LARGEST_POWER_OF_TEN: i16 = 4;
+
+    fn from_u128_bits(bits: u128) -> Self {
+        F16(u16::try_from(bits).unwrap())
+    }
+
+    fn to_u128_bits(self) -> u128 {
+        u128::from(self.0)
+    }
+}
+
+/// The bfloat16 format (the top half of an f32), stored as its bit pattern.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct BF16(pub u16);
+
+impl BF16 {
+    pub fn to_bits(self) -> u16 {
+        self.0
+    }
+}
+
+impl RawFloat for BF16 {
+    const MANTISSA_EXPLICIT_BITS: u32 = 7;
+    const EXPONENT_BITS: u32 = 8;
+    const SMALLEST_POWER_OF_TEN: i16 = -59;
+    const LARGEST_POWER_OF_TEN: i16 = 38;
+
+    fn from_u128_bits(bits: u128) -> Self {
+        BF16(u16::try_from(bits).unwrap())
+    }
+
+    fn to_u128_bits(self) -> u128 {
+        u128::from(self.0)
+    }
+}
+
+/// IEEE-754 binary128 (quad precision), stored as its bit pattern.
+#[cfg(feature = "f128")]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct F128(pub u128);
+
+#[cfg(feature = "f128")]
+impl F128 {
+    pub fn to_bits(self) -> u128 {
+        self.0
+    }
+}
+
+#[cfg(feature = "f128")]
+impl RawFloat for F128 {
+    const MANTISSA_EXPLICIT_BITS: u32 = 112;
+    const EXPONENT_BITS: u32 = 15;
+    const SMALLEST_POWER_OF_TEN: i16 = -4984;
+    const LARGEST_POWER_OF_TEN: i16 = 4932;
+    const MAX_DIGITS: usize = crate::decimal::QUAD_MAX_DIGITS;
+
+    fn from_u128_bits(bits: u128) -> Self {
+        F128(bits)
+    }
+
+    fn to_u128_bits(self) -> u128 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::{is_narrower, widen, BiasedFp, RawFloat, F16, BF16};
+    use crate::elparse::parse_float;
+
+    #[test]
+    fn check_derived_constants(){
+        assert_eq!(f64::EXPONENT_BIAS, 1023);
+        assert_eq!(f64::INFINITE_POWER, 0x7FF);
+        assert_eq!(f64::MINIMUM_EXPONENT, -1023);
+        assert_eq!(f32::EXPONENT_BIAS, 127);
+        assert_eq!(f32::INFINITE_POWER, 0xFF);
+    }
+
+    #[test]
+    fn check_from_biased_fp(){
+        let one = BiasedFp { f: 0, e: 1023 };
+        assert_eq!(f64::from_biased_fp(false, one), 1.0);
+        assert_eq!(f64::from_biased_fp(true, one), -1.0);
+        assert_eq!(f32::from_biased_fp(false, BiasedFp { f: 1 << 22, e: 127 }), 1.5);
+        assert_eq!(f32::from_biased_fp(true, BiasedFp::infinity::<f32>()), f32::NEG_INFINITY);
+        assert_eq!(f64::from_biased_fp(true, BiasedFp::zero()).to_bits(), (-0.0f64).to_bits());
+        assert_eq!(f64::from_biased_fp(false, BiasedFp { f: 1, e: 0 }), 4.9e-324);
+    }
+
+    fn f16_to_f64(bits: u16) -> f64 {
+        let exponent = i32::from((bits >> 10) & 0x1F);
+        let mantissa = f64::from(bits & 0x3FF);
+        let magnitude = if exponent == 0 {
+            mantissa * 2f64.powi(-24)
+        } else {
+            (1024.0 + mantissa) * 2f64.powi(exponent - 25)
+        };
+        if bits >> 15 != 0 { -magnitude } else { magnitude }
+    }
+
+    fn bf16_to_f64(bits: u16) -> f64 {
+        f64::from(f32::from_bits(u32::from(bits) << 16))
+    }
+
+    /* Walks every finite positive value of a 16-bit format and checks the
+    value itself, the exact midpoint to the next value (which must round to
+    even), and decimals just either side of the midpoint. Every midpoint is
+    exactly representable as an f64, and {:.150e} prints it exactly.
+    */
+    fn check_all_16bit<F: RawFloat>(to_f64: fn(u16) -> f64, wrap: fn(u16) -> F, max_finite: u16) {
+        for bits in 0..max_finite {
+            let lo = to_f64(bits);
+            let hi = to_f64(bits + 1);
+            let mid = format!("{:.150e}", (lo + hi) / 2.0);
+            let (mid_man, mid_exp) = mid.split_at(mid.find('e').unwrap());
+            let even = if bits & 1 == 0 { bits } else { bits + 1 };
+
+            let cases = [
+                (format!("{:e}", lo), bits),
+                (mid.clone(), even),
+                (format!("{}1{}", mid_man, mid_exp), bits + 1),
+                (format!("-{}1{}", mid_man, mid_exp), (bits + 1) | 0x8000),
+                (format!("{:e}", (lo + hi) / 2.0 - (hi - lo) / 1024.0), bits),
+            ];
+            for (input, expected) in cases.iter() {
+                let testout = parse_float::<F>(input).unwrap();
+                assert_eq!(testout, wrap(*expected), "Parsing {} should have given {:#06x}", input, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn check_all_f16(){
+        check_all_16bit::<F16>(f16_to_f64, F16, 0x7BFF);
+        assert_eq!(parse_float::<F16>("65519.99").unwrap(), F16(0x7BFF));
+        assert_eq!(parse_float::<F16>("65520").unwrap(), F16(0x7C00));
+        assert_eq!(parse_float::<F16>("-1e5").unwrap(), F16(0xFC00));
+        assert_eq!(parse_float::<F16>("2.98e-8").unwrap(), F16(0));
+        assert_eq!(parse_float::<F16>("-2.99e-8").unwrap(), F16(0x8001));
+    }
+
+    #[test]
+    fn check_widen(){
+        // Every non-NaN binary16 value, including subnormals and infinities
+        for bits in (0..=0xFFFFu16).filter(|b| b & 0x7C00 != 0x7C00 || b & 0x3FF == 0) {
+            let expected = if bits & 0x7FFF == 0x7C00 { if bits >> 15 == 0 { f64::INFINITY } else { f64::NEG_INFINITY } } else { f16_to_f64(bits) };
+            let testout: f64 = widen(F16(bits));
+            assert_eq!(testout.to_bits(), expected.to_bits(), "Widening {:#x} should have given {:e} but got {:e}", bits, expected, testout);
+        }
+        for _ in 0..100_000 {
+            let x = f32::from_bits(rand::random::<u32>());
+            if !x.is_nan() {
+                assert_eq!(widen::<f32, f64>(x).to_bits(), f64::from(x).to_bits(), "Widening {:e} gave the wrong result", x);
+            }
+        }
+        assert!(is_narrower::<f32, f64>());
+        assert!(!is_narrower::<f32, BF16>());
+        assert!(!is_narrower::<f64, f64>());
+    }
+
+    #[test]
+    fn check_all_bf16(){
+        check_all_16bit::<BF16>(bf16_to_f64, BF16, 0x7F7F);
+        assert_eq!(parse_float::<BF16>("1e39").unwrap(), BF16(0x7F80));
+        assert_eq!(parse_float::<BF16>("3.14159").unwrap(), BF16(0x4049));
+    }
+
+    #[cfg(feature = "f128")]
+    mod quad {
+        use super::super::F128;
+        use crate::elparse::{parse_float, parse_float_with_grammar};
+        use crate::grammar::Grammar;
+        use num_bigint::BigInt;
+        use num_rational::BigRational;
+        use num_traits::{One, Zero};
+        use rand::random;
+        use std::convert::{TryFrom, TryInto};
+
+        /// Exactly rounds a decimal (digits, optional point, optional
+        /// exponent) to binary128 using rational arithmetic.
+        fn reference_f128(input: &str) -> u128 {
+            let (neg, input) = match input.strip_prefix('-') {
+                Some(rest) => (true, rest),
+                None => (false, input),
+            };
+            let (man, e10) = match input.find('e') {
+                Some(i) => (&input[..i], input[i + 1..].parse::<i32>().unwrap()),
+                None => (input, 0),
+            };
+            let frac_digits = man.find('.').map_or(0, |i| man.len() - i - 1);
+            let digits: BigInt = man.replace('.', "").parse().unwrap();
+            let e10 = e10 - i32::try_from(frac_digits).unwrap();
+            let pow10 = BigInt::from(10).pow(e10.unsigned_abs());
+            let value = if e10 >= 0 {
+                BigRational::from_integer(digits * pow10)
+            } else {
+                BigRational::new(digits, pow10)
+            };
+            let sign = if neg { 1u128 << 127 } else { 0 };
+            if value.is_zero() {
+                return sign;
+            }
+
+            // Find e2 with 2 ** e2 <= value < 2 ** (e2 + 1), clamped to the normal range
+            let mut e2 = i64::try_from(value.numer().bits()).unwrap() - i64::try_from(value.denom().bits()).unwrap();
+            let pow2 = |e: i64| {
+                let p = BigInt::one() << e.unsigned_abs();
+                if e >= 0 { BigRational::from_integer(p) } else { BigRational::new(BigInt::one(), p) }
+            };
+            while pow2(e2) > value {
+                e2 -= 1;
+            }
+            while pow2(e2 + 1) <= value {
+                e2 += 1;
+            }
+            let e2 = e2.max(-16382);
+
+            // Round value / 2 ** (e2 - 112) to an integer, ties to even
+            let scaled = value / pow2(e2 - 112);
+            let floor = scaled.floor();
+            let rem = &scaled - &floor;
+            let half = BigRational::new(BigInt::one(), BigInt::from(2));
+            let mut m = floor.to_integer();
+            if rem > half || (rem == half && m.bit(0)) {
+                m += 1;
+            }
+
+            let mut biased = e2 + 16383;
+            if m.bits() > 113 {
+                m >>= 1;
+                biased += 1;
+            }
+            if biased >= 0x7FFF {
+                return sign | (0x7FFFu128 << 112);
+            }
+            let m: u128 = m.try_into().unwrap();
+            if m >> 112 == 0 {
+                // Subnormal
+                return sign | m;
+            }
+            sign | (u128::try_from(biased).unwrap() << 112) | (m & ((1 << 112) - 1))
+        }
+
+        #[test]
+        fn check_known_f128(){
+            let cases = [
+                ("1", 0x3FFF0000000000000000000000000000u128),
+                ("-2", 0xC0000000000000000000000000000000),
+                ("0.1", 0x3FFB999999999999999999999999999A),
+                ("3.14159265358979323846264338327950288", 0x4000921FB54442D18469898CC51701B8),
+                ("1.18973149535723176508575932662800702e4932", 0x7FFEFFFFFFFFFFFFFFFFFFFFFFFFFFFF),
+                ("1.2e4932", 0x7FFF0000000000000000000000000000),
+                ("6.4751751194380251109244389582276465525e-4966", 0x1),
+                ("3e-4966", 0x0),
+                ("-0", 0x80000000000000000000000000000000),
+            ];
+            for (i, o) in cases.iter() {
+                let testout = parse_float::<F128>(i).unwrap();
+                assert_eq!(testout, F128(*o), "Parsing {} should have given {:#x} but got {:#x}", i, o, testout.to_bits());
+                assert_eq!(reference_f128(i), *o, "Reference for {} disagrees", i);
+            }
+        }
+
+        #[test]
+        fn check_f128_suffixes(){
+            // Suffixes narrower than binary128 round to that type first
+            assert_eq!(parse_float::<F128>("0.1f64").unwrap(), F128(0x3FFB999999999999A000000000000000));
+            assert_eq!(parse_float::<F128>("0.1f32").unwrap(), F128(0x3FFB99999A0000000000000000000000));
+            assert_eq!(parse_float::<F128>("4.9e-324f64").unwrap(), F128(0x3BCD0000000000000000000000000000));
+        }
+
+        #[test]
+        fn check_f128_hex(){
+            let hex = |x: &str| parse_float_with_grammar::<F128>(x.as_bytes(), Grammar::C).unwrap();
+            assert_eq!(hex("0x1.ffffffffffffffffffffffffffffp0"), F128(0x3FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF));
+            assert_eq!(hex("0x1.ffffffffffffffffffffffffffff8p0"), F128(0x40000000000000000000000000000000));
+            assert_eq!(hex("-0x1.8p3"), F128(0xC0028000000000000000000000000000));
+            assert_eq!(hex("0x1p-16494"), F128(1));
+        }
+
+        #[test]
+        fn check_random_f128(){
+            for _ in 0..2_000 {
+                let ndigits = 1 + random::<usize>() % 45;
+                let digits: String = (0..ndigits).map(|_| char::from(b'0' + random::<u8>() % 10)).collect();
+                let e10 = random::<i16>() % 5000;
+                let input = format!("{}e{}", digits, e10);
+                let expected = reference_f128(&input);
+                let testout = parse_float::<F128>(&input).unwrap();
+                assert_eq!(testout, F128(expected), "Parsing {} should have given {:#x} but got {:#x}", input, expected, testout.to_bits());
+            }
+        }
+    }
+}
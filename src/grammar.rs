@@ -0,0 +1,412 @@
+/* The textual forms of floats that the parsers in `crate::elparse` accept.
+
+A `Grammar` names a complete set of syntax rules for some language or file
+format. Each grammar is described by a `Format`, the individual rules that
+the scanner in `elparse::parse_parts` checks as it goes, so callers with
+unusual inputs can also build a `Format` of their own.
+*/
+
+/// A set of syntax rules for floats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Grammar {
+    /// The crate's own grammar: Rust float literals, loosened to also allow
+    /// a leading sign, integers (`123`), a leading point (`.5`), underscores
+    /// anywhere, and `inf`, `infinity` and `nan` in any case as `str::parse`
+    /// accepts them.
+    #[default]
+    Loose,
+    /// Exactly the float literals rustc's lexer accepts: `1.`, `1.5`,
+    /// `1e10`, `1_000.0`, `1e_10` and `1f32`, but not `-1.0`, `.5`, `1.e5`
+    /// or `123`.
+    RustLiteral,
+    /// JSON numbers (RFC 8259): `-0.5e+10`, but not `+1`, `01`, `1.` or `.5`.
+    Json,
+    /// C's `strtod`: leading whitespace, a sign, `.5` and `5.` are allowed,
+    /// as are hex floats (`0x1.8p3`) and `inf`, `infinity` and `nan(...)` in
+    /// any case.
+    C,
+    /// Python's `float()`: surrounding whitespace, a sign, `.5` and `5.`,
+    /// single underscores between digits, and `inf`, `infinity` and `nan` in
+    /// any case.
+    Python,
+    /// TOML floats: digits on both sides of a point and of each underscore,
+    /// no leading zeros, and a point or exponent; or lower case `inf` and
+    /// `nan`.
+    Toml,
+}
+
+/// Where underscores may appear among the digits of a number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Underscores {
+    Never,
+    Anywhere,
+    /// In the mantissa, anywhere after the first digit of the integer or
+    /// fraction digits; in the exponent, anywhere.
+    AfterDigit,
+    /// Only singly, with a digit on each side.
+    BetweenDigits,
+}
+
+/// Which spellings of infinity and NaN a grammar accepts. `inf` and `nan`
+/// are always accepted, with a sign if the grammar allows one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpecialValues {
+    /// Upper and mixed case, as in `NaN` and `INF`
+    pub any_case: bool,
+    /// `infinity` as well as `inf`
+    pub infinity: bool,
+    /// A NaN payload in parentheses, either `0x` and hex digits or decimal
+    /// digits, as in `nan(0x7ff)`
+    pub nan_payload: bool,
+}
+
+/// The individual rules of a grammar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Format {
+    /// A leading `+` is allowed
+    pub plus_sign: bool,
+    /// A leading `-` is allowed
+    pub minus_sign: bool,
+    /// The mantissa must have digits before any point
+    pub integer_digits: bool,
+    /// The integer digits may start with a zero, as in `01.5`
+    pub leading_zeros: bool,
+    /// A point must be followed by a digit
+    pub fraction_digits: bool,
+    /// A point without digits after it must end the number: no exponent or
+    /// suffix may follow
+    pub bare_point_ends: bool,
+    /// A number without a point or exponent must have a suffix
+    pub point_or_exponent: bool,
+    pub underscores: Underscores,
+    /// `f32` and `f64` suffixes are allowed
+    pub suffixes: bool,
+    /// Whitespace before the number is skipped
+    pub leading_whitespace: bool,
+    /// Whitespace after the number is skipped
+    pub trailing_whitespace: bool,
+    /// Infinity and NaN, if they are allowed at all
+    pub special_values: Option<SpecialValues>,
+    /// Hexadecimal floats such as `0x1.8p3`. As with `strtod`, the binary
+    /// exponent is optional; the mantissa takes no underscores and needs a
+    /// digit on one side of the point.
+    pub hex_floats: bool,
+}
+
+impl Grammar {
+    pub fn format(self) -> Format {
+        // The rules that almost every grammar shares
+        let base = Format {
+            plus_sign: true,
+            minus_sign: true,
+            integer_digits: false,
+            leading_zeros: true,
+            fraction_digits: false,
+            bare_point_ends: false,
+            point_or_exponent: false,
+            underscores: Underscores::Never,
+            suffixes: false,
+            leading_whitespace: false,
+            trailing_whitespace: false,
+            special_values: None,
+            hex_floats: false,
+        };
+        // Rust's and C's spellings of infinity and NaN
+        let any_case = SpecialValues { any_case: true, infinity: true, nan_payload: false };
+        match self {
+            Grammar::Loose => Format {
+                underscores: Underscores::Anywhere,
+                suffixes: true,
+                special_values: Some(any_case),
+                ..base
+            },
+            Grammar::RustLiteral => Format {
+                plus_sign: false,
+                minus_sign: false,
+                integer_digits: true,
+                bare_point_ends: true,
+                point_or_exponent: true,
+                underscores: Underscores::AfterDigit,
+                suffixes: true,
+                ..base
+            },
+            Grammar::Json => Format {
+                plus_sign: false,
+                integer_digits: true,
+                leading_zeros: false,
+                fraction_digits: true,
+                ..base
+            },
+            Grammar::C => Format {
+                leading_whitespace: true,
+                special_values: Some(SpecialValues { nan_payload: true, ..any_case }),
+                hex_floats: true,
+                ..base
+            },
+            Grammar::Python => Format {
+                underscores: Underscores::BetweenDigits,
+                leading_whitespace: true,
+                trailing_whitespace: true,
+                special_values: Some(any_case),
+                ..base
+            },
+            Grammar::Toml => Format {
+                integer_digits: true,
+                leading_zeros: false,
+                fraction_digits: true,
+                point_or_exponent: true,
+                underscores: Underscores::BetweenDigits,
+                special_values: Some(SpecialValues { any_case: false, infinity: false, nan_payload: false }),
+                ..base
+            },
+        }
+    }
+}
+
+impl From<Grammar> for Format {
+    fn from(grammar: Grammar) -> Format {
+        grammar.format()
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::Grammar;
+    use crate::elparse::{parse_float, parse_float_with_grammar, parse_float_partial_with_grammar};
+    use crate::error::ParseError::{self, *};
+
+    fn check_grammar(grammar: Grammar, cases: &[(&str, Result<f64, ParseError>)]) {
+        for (i, o) in cases.iter() {
+            let testout = parse_float_with_grammar::<f64>(i.as_bytes(), grammar);
+            assert_eq!(testout.map(f64::to_bits), o.map(f64::to_bits),
+                       "Parsing {:?} in {:?} should have given {:?} but got {:?}", i, grammar, o, testout);
+        }
+    }
+
+    // Float literal tokens as rustc's lexer sees them
+    #[test]
+    fn check_rust_literal_grammar(){
+        check_grammar(Grammar::RustLiteral, &[
+            // Accepted
+            ("1.", Ok(1.0)),
+            ("1.5", Ok(1.5)),
+            ("0.1", Ok(0.1)),
+            ("007.5", Ok(7.5)),
+            ("1e10", Ok(1e10)),
+            ("1E-10", Ok(1e-10)),
+            ("2.5e+3", Ok(2.5e3)),
+            ("1_000.0", Ok(1000.0)),
+            ("1__000_.0_", Ok(1000.0)),
+            ("1_e5", Ok(1e5)),
+            ("1e_10", Ok(1e10)),
+            ("1e+_1_0_", Ok(1e10)),
+            ("1.5E__3", Ok(1.5e3)),
+            ("1f32", Ok(1.0)),
+            ("1_f64", Ok(1.0)),
+            ("1.5f32", Ok(1.5)),
+            ("1.5_f64", Ok(1.5)),
+            ("1e5f64", Ok(1e5)),
+            ("1e5_f32", Ok(1e5)),
+            // Signs are unary operators, not part of the literal
+            ("-1.0", Err(InvalidSign(0))),
+            ("+1.0", Err(InvalidSign(0))),
+            // Integers, and things that are not literals at all
+            ("123", Err(MissingPointOrExponent(3))),
+            ("1_000", Err(MissingPointOrExponent(5))),
+            ("", Err(Empty)),
+            (".5", Err(MissingDigits(0))),
+            ("_1.0", Err(InvalidDigit(0))),
+            // A period without digits after it must end the literal
+            ("1.e5", Err(InvalidDigit(2))),
+            ("1.f32", Err(InvalidDigit(2))),
+            ("1._5", Err(InvalidDigit(2))),
+            ("1..", Err(MultipleDecimalPoints(2))),
+            ("1.5.", Err(MultipleDecimalPoints(3))),
+            // Exponents need a digit
+            ("1e", Err(MissingExponentDigits(2))),
+            ("1e_", Err(MissingExponentDigits(3))),
+            ("1.5e+", Err(MissingExponentDigits(5))),
+            ("1e+-1", Err(MissingExponentDigits(3))),
+            // Only float suffixes, in lower case
+            ("1.5f16", Err(InvalidDigit(3))),
+            ("1.5F32", Err(InvalidDigit(3))),
+            ("1u8", Err(InvalidDigit(1))),
+            ("1.5 ", Err(InvalidDigit(3))),
+            // Not literals either
+            ("inf", Err(InvalidDigit(0))),
+            ("NaN", Err(InvalidDigit(0))),
+        ]);
+
+        // The f32 suffix means f32 rounding here too
+        let testout = parse_float_with_grammar::<f64>(b"1.00000005960464477550f32", Grammar::RustLiteral);
+        assert_eq!(testout, Ok(f64::from(1.0000001f32)));
+
+        assert_eq!(parse_float_partial_with_grammar::<f64>(b"1.5..2.5", Grammar::RustLiteral), Ok((1.5, 3)));
+        assert_eq!(parse_float_partial_with_grammar::<f64>(b"1.e5", Grammar::RustLiteral), Ok((1.0, 2)));
+        assert_eq!(parse_float_partial_with_grammar::<f64>(b"2e_x", Grammar::RustLiteral), Err(MissingPointOrExponent(1)));
+
+        // The loose grammar accepts all of these
+        for i in ["-1.0", "123", ".5", "1.e5", "1.f32", "_1.0", "1e_10"].iter() {
+            assert!(parse_float::<f64>(i).is_ok(), "{} should parse in the loose grammar", i);
+        }
+    }
+
+    #[test]
+    fn check_json_grammar(){
+        check_grammar(Grammar::Json, &[
+            ("0", Ok(0.0)),
+            ("-0", Ok(-0.0)),
+            ("1", Ok(1.0)),
+            ("-1.5", Ok(-1.5)),
+            ("0.5", Ok(0.5)),
+            ("1e5", Ok(1e5)),
+            ("1E+5", Ok(1e5)),
+            ("1e-05", Ok(1e-5)),
+            ("-123.456e7", Ok(-123.456e7)),
+            ("+1", Err(InvalidSign(0))),
+            ("01", Err(InvalidDigit(1))),
+            ("00", Err(InvalidDigit(1))),
+            ("-01.5", Err(InvalidDigit(2))),
+            ("1.", Err(MissingDigits(2))),
+            ("1.e5", Err(MissingDigits(2))),
+            (".5", Err(MissingDigits(0))),
+            ("-.5", Err(MissingDigits(1))),
+            ("1_000", Err(InvalidDigit(1))),
+            ("1e", Err(MissingExponentDigits(2))),
+            ("1.5f32", Err(InvalidDigit(3))),
+            (" 1", Err(InvalidDigit(0))),
+            ("1 ", Err(InvalidDigit(1))),
+            ("0x10", Err(InvalidDigit(1))),
+            ("0x1p3", Err(InvalidDigit(1))),
+            ("Infinity", Err(InvalidDigit(0))),
+            ("NaN", Err(InvalidDigit(0))),
+        ]);
+        assert_eq!(parse_float_partial_with_grammar::<f64>(b"1.5,", Grammar::Json), Ok((1.5, 3)));
+        assert_eq!(parse_float_partial_with_grammar::<f64>(b"01", Grammar::Json), Ok((0.0, 1)));
+        assert_eq!(parse_float_partial_with_grammar::<f64>(b"2.]", Grammar::Json), Ok((2.0, 1)));
+        assert_eq!(parse_float_partial_with_grammar::<f64>(b"[1", Grammar::Json), Err(InvalidDigit(0)));
+    }
+
+    #[test]
+    fn check_c_grammar(){
+        check_grammar(Grammar::C, &[
+            ("  1.5", Ok(1.5)),
+            ("\t\n-2", Ok(-2.0)),
+            ("+.5", Ok(0.5)),
+            ("5.", Ok(5.0)),
+            ("5.e3", Ok(5e3)),
+            ("007", Ok(7.0)),
+            ("1E-2", Ok(1e-2)),
+            ("1.5 ", Err(InvalidDigit(3))),
+            ("1_0", Err(InvalidDigit(1))),
+            ("1.5f", Err(InvalidDigit(3))),
+            ("", Err(Empty)),
+            ("   ", Err(Empty)),
+            ("e5", Err(MissingDigits(0))),
+            ("1e+", Err(MissingExponentDigits(3))),
+            (" INFINITY", Ok(f64::INFINITY)),
+            ("-Inf", Ok(f64::NEG_INFINITY)),
+            ("nan", Ok(f64::NAN)),
+            ("nan()", Ok(f64::NAN)),
+            ("nan(0x1234)", Ok(f64::from_bits(0x7ff8_0000_0000_1234))),
+            ("-NAN(0Xabc)", Ok(f64::from_bits(0xfff8_0000_0000_0abc))),
+            ("nan(42)", Ok(f64::from_bits(0x7ff8_0000_0000_002a))),
+            // Payload bits that do not fit below the quiet bit are dropped
+            ("nan(0xfffffffffffffffff)", Ok(f64::from_bits(0x7fff_ffff_ffff_ffff))),
+            // Any n-char-sequence is consumed; only numbers are payloads
+            ("nan(0x)", Ok(f64::NAN)),
+            ("nan(abc)", Ok(f64::NAN)),
+            ("nan(0x12_z)", Ok(f64::NAN)),
+            ("NaN(_1a)", Ok(f64::NAN)),
+            ("nan(12", Err(InvalidDigit(3))),
+            ("nan(a-b)", Err(InvalidDigit(3))),
+            ("0x1.8p3", Ok(12.0)),
+            (" -0x.1P-4", Ok(-1.0 / 256.0)),
+            ("0x1.8", Ok(1.5)),
+            ("0x1p", Err(MissingExponentDigits(4))),
+            ("0x1.8.", Err(MultipleDecimalPoints(5))),
+            ("0x1p3.", Err(InvalidDigit(5))),
+            ("0x1e.8p3.", Err(MultipleDecimalPoints(8))),
+            ("0x1g", Err(InvalidDigit(3))),
+            ("0x", Err(InvalidDigit(1))),
+            ("0x.p1", Err(InvalidDigit(1))),
+        ]);
+        // Like strtod, skipped whitespace counts as consumed
+        assert_eq!(parse_float_partial_with_grammar::<f64>(b"  3.5abc", Grammar::C), Ok((3.5, 5)));
+        assert_eq!(parse_float_partial_with_grammar::<f64>(b"1e+x", Grammar::C), Ok((1.0, 1)));
+        assert_eq!(parse_float_partial_with_grammar::<f64>(b" infinite", Grammar::C), Ok((f64::INFINITY, 4)));
+        let nan = parse_float_partial_with_grammar::<f32>(b"nan(0x5)x", Grammar::C);
+        assert_eq!(nan.map(|(x, len)| (x.to_bits(), len)), Ok((0x7fc0_0005, 8)));
+        assert_eq!(parse_float_partial_with_grammar::<f64>(b"0x1.8p+x", Grammar::C), Ok((1.5, 5)));
+        assert_eq!(parse_float_partial_with_grammar::<f64>(b"0xg", Grammar::C), Ok((0.0, 1)));
+        let nan = parse_float_partial_with_grammar::<f32>(b"nan(abc)x", Grammar::C);
+        assert_eq!(nan.map(|(x, len)| (x.to_bits(), len)), Ok((0x7fc0_0000, 8)));
+        let nan = parse_float_partial_with_grammar::<f32>(b"nan(0x", Grammar::C);
+        assert_eq!(nan.map(|(x, len)| (x.to_bits(), len)), Ok((0x7fc0_0000, 3)));
+    }
+
+    #[test]
+    fn check_python_grammar(){
+        check_grammar(Grammar::Python, &[
+            (" 1.5 ", Ok(1.5)),
+            ("\n-2\t", Ok(-2.0)),
+            ("1_000", Ok(1000.0)),
+            ("1_000.000_1", Ok(1000.0001)),
+            ("1e1_0", Ok(1e10)),
+            ("+.5", Ok(0.5)),
+            ("5.", Ok(5.0)),
+            ("0001.5", Ok(1.5)),
+            ("1E5", Ok(1e5)),
+            ("1__000", Err(InvalidDigit(1))),
+            ("_1", Err(InvalidDigit(0))),
+            ("1_", Err(InvalidDigit(1))),
+            ("1_.5", Err(InvalidDigit(1))),
+            ("1._5", Err(InvalidDigit(2))),
+            ("1e_5", Err(MissingExponentDigits(2))),
+            ("1.5f32", Err(InvalidDigit(3))),
+            ("1 5", Err(InvalidDigit(2))),
+            ("", Err(Empty)),
+            (" ", Err(Empty)),
+            (" -inf ", Ok(f64::NEG_INFINITY)),
+            ("+Infinity", Ok(f64::INFINITY)),
+            ("nAn\n", Ok(f64::NAN)),
+            ("nan(1)", Err(InvalidDigit(3))),
+            ("in f", Err(InvalidDigit(0))),
+        ]);
+    }
+
+    #[test]
+    fn check_toml_grammar(){
+        // Mostly examples from the TOML 1.0 specification
+        check_grammar(Grammar::Toml, &[
+            ("+1.0", Ok(1.0)),
+            ("3.25", Ok(3.25)),
+            ("-0.01", Ok(-0.01)),
+            ("5e+22", Ok(5e22)),
+            ("1e06", Ok(1e6)),
+            ("-2E-2", Ok(-2e-2)),
+            ("6.626e-34", Ok(6.626e-34)),
+            ("224_617.445_991_228", Ok(224617.445991228)),
+            ("-0.0", Ok(-0.0)),
+            ("7", Err(MissingPointOrExponent(1))),
+            (".7", Err(MissingDigits(0))),
+            ("7.", Err(MissingDigits(2))),
+            ("3.e+20", Err(MissingDigits(2))),
+            ("01.5", Err(InvalidDigit(1))),
+            ("0_1.5", Err(InvalidDigit(1))),
+            ("1__0.0", Err(InvalidDigit(1))),
+            ("1.5_", Err(InvalidDigit(3))),
+            ("1e_5", Err(MissingExponentDigits(2))),
+            ("1.5f64", Err(InvalidDigit(3))),
+            (" 1.0", Err(InvalidDigit(0))),
+            ("inf", Ok(f64::INFINITY)),
+            ("+inf", Ok(f64::INFINITY)),
+            ("-inf", Ok(f64::NEG_INFINITY)),
+            ("nan", Ok(f64::NAN)),
+            ("-nan", Ok(-f64::NAN)),
+            ("Inf", Err(InvalidDigit(0))),
+            ("NaN", Err(InvalidDigit(0))),
+            ("infinity", Err(InvalidDigit(3))),
+        ]);
+    }
+}
@@ -1,2 +1,14 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+// Tests always link std, so they cover batch without the alloc feature
+#[cfg(any(feature = "alloc", test))]
+extern crate alloc;
+
 pub mod lookups;
 pub mod elparse;
+pub mod decimal;
+pub mod float;
+pub mod error;
+pub mod grammar;
+#[cfg(any(feature = "alloc", test))]
+pub mod batch;
@@ -1,15 +1,26 @@
-use std::convert::{TryFrom, TryInto};
-use std::env;
 
 /* This file (generated by build.rs) generates two variables for our usage:
 EL_POW10_LUT_MIN, which defines the minimum power of ten (e10) contained in the
 lookup table, and EL_POW10_LUT_MIN, which defines the actual lookup table.
 
+With the `small-table` feature there is no EL_POW10_LUT. Instead the powers
+10 ** (27 * k) are stored in EL_POW10_ANCHORS, and the powers in between are anchor
+times 5 ** r (the 2 ** r part of 10 ** r only moves the binary point), with
+a two-bit correction per power for the truncation of the anchor. That is
+under 1 KB rather than about 10 KB, for one extra multiply per lookup.
+
+The range of the table can be set when building, with the
+EISEL_LEMIRE_LUT_MIN and EISEL_LEMIRE_LUT_MAX environment variables, and
+EISEL_LEMIRE_LUT_WIDTH=64 keeps only the high 64 bits of each power (the
+eisel_lemire_lut64 cfg). Powers the table does not have are simply missing:
+the lookups return None, and parsing falls back to the decimal algorithm.
+
 This module contains the resulting lookup functions for the Eisel-Lemire
 algorithm, along with some tests to validate sanity of the lookup table.
 */
 include!(concat!(env!("OUT_DIR"), "/el_lookup_table.rs"));
 
+#[cfg(test)]
 const BIAS: i16 = 1214;
 
 // All functions in this module return None if the lookup is not present in the
@@ -23,33 +34,109 @@ pub const fn lut_e10_max() -> i16 {
     EL_POW10_LUT_MAX
 }
 
-pub fn compute_index(e10: i16) -> Option<usize> {
-    usize::try_from(e10 - EL_POW10_LUT_MIN).ok()
+/// The bits stored per power of ten: 128, or 64 if get_m128_lo is unavailable
+pub const fn lut_entry_width() -> u32 {
+    EL_POW10_LUT_WIDTH
+}
+
+// These are const fns for `elparse::parse_f64_const`, hence `as` rather than
+// TryFrom, and match rather than `?`.
+
+pub const fn compute_index(e10: i16) -> Option<usize> {
+    if e10 < EL_POW10_LUT_MIN || e10 > EL_POW10_LUT_MAX {
+        return None;
+    }
+    Some((e10 as i32 - EL_POW10_LUT_MIN as i32) as usize)
+}
+
+/// The high and low halves of the 128-bit power of ten
+#[cfg(not(any(feature = "small-table", eisel_lemire_lut64)))]
+const fn get_m128(e10: i16) -> Option<(u64, u64)> {
+    match compute_index(e10) {
+        Some(index) => Some(EL_POW10_LUT[index]),
+        None => None,
+    }
 }
 
-pub fn get_m64(e10: i16) -> Option<u64> {
-    Some(EL_POW10_LUT[compute_index(e10)?].0)
+/// The high and low halves of the 128-bit power of ten, reconstructed from
+/// the anchor power below it
+#[cfg(feature = "small-table")]
+const fn get_m128(e10: i16) -> Option<(u64, u64)> {
+    let index = match compute_index(e10) {
+        Some(index) => index,
+        None => return None,
+    };
+    // 10 ** e10 = 10 ** anchor * 5 ** r * 2 ** r
+    let offset = e10 - EL_POW10_ANCHOR_MIN;
+    let (anchor_hi, anchor_lo) = EL_POW10_ANCHORS[(offset / EL_POW10_ANCHOR_STEP) as usize];
+    let pow5 = EL_POW5[(offset % EL_POW10_ANCHOR_STEP) as usize] as u128;
+
+    // The top 128 bits of the 192-bit product, normalized. The anchor's high
+    // bit is set and pow5 < 2 ** 61, so the shift is between 3 and 64.
+    let lo_prod = anchor_lo as u128 * pow5;
+    let top = anchor_hi as u128 * pow5 + (lo_prod >> 64);
+    let lz = top.leading_zeros();
+    let m128 = (top << lz) | ((lo_prod as u64) >> (64 - lz)) as u128;
+
+    // The anchor is truncated, so that is up to 3 too low
+    let correction = (EL_POW10_CORRECTIONS[index / 32] >> (2 * (index % 32))) & 3;
+    let m128 = m128 + correction as u128;
+    Some(((m128 >> 64) as u64, m128 as u64))
 }
 
-pub fn get_m128_hi(e10: i16) -> Option<u64> {
+#[cfg(not(eisel_lemire_lut64))]
+pub const fn get_m64(e10: i16) -> Option<u64> {
+    match get_m128(e10) {
+        Some((hi, _)) => Some(hi),
+        None => None,
+    }
+}
+
+#[cfg(eisel_lemire_lut64)]
+pub const fn get_m64(e10: i16) -> Option<u64> {
+    match compute_index(e10) {
+        Some(index) => Some(EL_POW10_LUT[index]),
+        None => None,
+    }
+}
+
+pub const fn get_m128_hi(e10: i16) -> Option<u64> {
     get_m64(e10)
 }
 
-pub fn get_m128_lo(e10: i16) -> Option<u64> {
-    Some(EL_POW10_LUT[compute_index(e10)?].1)
+#[cfg(not(eisel_lemire_lut64))]
+pub const fn get_m128_lo(e10: i16) -> Option<u64> {
+    match get_m128(e10) {
+        Some((_, lo)) => Some(lo),
+        None => None,
+    }
 }
 
-pub fn get_widebiased_e2(e10: i16) -> Option<i16> {
-    let _index = compute_index(e10)?;
-    let e10: i64 = e10.into();
-    let exp = ((217706i64 * e10) >> 16) + 1087;
-    exp.try_into().ok()
+#[cfg(eisel_lemire_lut64)]
+pub const fn get_m128_lo(_e10: i16) -> Option<u64> {
+    None
 }
 
-pub fn get_narrowbiased_e2(e10: i16) -> Option<i16> {
-    Some(get_widebiased_e2(e10)? + 64)
+pub const fn get_widebiased_e2(e10: i16) -> Option<i16> {
+    if compute_index(e10).is_none() {
+        return None;
+    }
+    // 14267572527 / 2 ** 32 approximates log2(10) closely enough to give the
+    // exact floor over the whole binary128 range. (The classic 217706 / 2 ** 16
+    // is only good to about +/- 4000.) That also keeps the result well inside
+    // the i16 range.
+    let exp = ((14267572527i64 * e10 as i64) >> 32) + 1087;
+    Some(exp as i16)
 }
 
+pub const fn get_narrowbiased_e2(e10: i16) -> Option<i16> {
+    match get_widebiased_e2(e10) {
+        Some(e2) => Some(e2 + 64),
+        None => None,
+    }
+}
+
+#[cfg(feature = "std")]
 pub fn print_stuff() {
     println!("Hey there!")
 }
@@ -57,6 +144,7 @@ pub fn print_stuff() {
 #[cfg(test)]
 pub mod tests {
     use super::*;
+    use crate::float::RawFloat;
     use num_bigint::BigInt;
     use num_rational::BigRational;
     use num_traits::One;
@@ -97,16 +185,22 @@ pub mod tests {
         }
     }
 
+    /// Whether the fast path can round inputs with this power of ten in the
+    /// format F: the table, perhaps narrowed at build time, has the power,
+    /// or it is beyond the range of F and needs none. The fast path leaves
+    /// other inputs to the decimal fallback.
+    pub fn lut_covers<F: RawFloat>(e10: i16) -> bool {
+        (lut_e10_min()..=lut_e10_max()).contains(&e10) || e10 < F::SMALLEST_POWER_OF_TEN || e10 > F::LARGEST_POWER_OF_TEN
+    }
+
     fn lut_e10_values() -> Vec<i16> {
-        let num_lut_entries: i16 = EL_POW10_LUT.len().try_into().unwrap();
-        let lut_e10_range = EL_POW10_LUT_MIN..EL_POW10_LUT_MIN + num_lut_entries;
-        lut_e10_range.collect()
+        (lut_e10_min()..=lut_e10_max()).collect()
     }
 
     #[test]
     pub fn lut_highbit_set() {
-        for entry in EL_POW10_LUT {
-            assert!(entry.0 & (1u64 << 63) != 0, "Zero high bit in LUT");
+        for e10 in lut_e10_values() {
+            assert!(get_m64(e10).unwrap() & (1u64 << 63) != 0, "Zero high bit in LUT for 10**{}", e10);
         }
     }
 
@@ -119,9 +213,10 @@ pub mod tests {
                 "Failed to get M128_hi for 10**{}",
                 e10
             );
-            assert!(
+            assert_eq!(
                 get_m128_lo(e10).is_some(),
-                "Failed to get M128_lo for 10**{}",
+                lut_entry_width() == 128,
+                "M128_lo presence does not match the table width for 10**{}",
                 e10
             );
             assert!(
@@ -148,7 +243,7 @@ pub mod tests {
         for e10 in lut_e10_values() {
             let m64 = get_m64(e10).expect("Missing lut value.");
             let e2 = get_narrowbiased_e2(e10).unwrap();
-            let e2: i16 = i16::try_from(e2).unwrap() - BIAS;
+            let e2: i16 = e2 - BIAS;
 
             let pow10 = compute_pow_10(e10);
             let m64_times_pow2 = compute_mult_pow2(BigInt::from(m64), e2);
@@ -183,10 +278,17 @@ pub mod tests {
     pub fn m128_bounds_satisfied() {
         for e10 in lut_e10_values() {
             let m128hi = get_m128_hi(e10).expect("Missing lut value.");
-            let m128lo = get_m128_lo(e10).expect("Missing lut value.");
+            // A 64-bit table has only M64, whose bounds are checked above
+            let m128lo = match get_m128_lo(e10) {
+                Some(m128lo) => m128lo,
+                None => {
+                    assert_eq!(lut_entry_width(), 64, "Missing lut value for 10**{}", e10);
+                    continue;
+                }
+            };
             let m128_bint = u128_to_bigint(m128hi, m128lo);
             let e2 = get_widebiased_e2(e10).unwrap();
-            let e2: i16 = i16::try_from(e2).unwrap() - BIAS;
+            let e2: i16 = e2 - BIAS;
 
             let pow10 = compute_pow_10(e10);
             let m64_times_pow2 = compute_mult_pow2(m128_bint.clone(), e2);